@@ -1,160 +1,2376 @@
-use std::cmp::Ordering;
+use crate::encoding::{base64_encode, decode_text, encode_unsafe, percent_encode, strip_bom};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
-use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+    Options,
+}
+
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Self::Get => "GET",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Patch => "PATCH",
+            Self::Delete => "DELETE",
+            Self::Head => "HEAD",
+            Self::Options => "OPTIONS",
+        }
+    }
+
+    /// Whether sending the request twice has the same effect as sending it
+    /// once: true for everything but `POST` and `PATCH`.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(*self, Self::Post | Self::Patch)
+    }
+}
+
+impl std::fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for HttpMethod {
+    type Err = HttpErrorKind;
 
-#[derive(Debug, Serialize, Deserialize)]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "GET" => Ok(Self::Get),
+            "POST" => Ok(Self::Post),
+            "PUT" => Ok(Self::Put),
+            "PATCH" => Ok(Self::Patch),
+            "DELETE" => Ok(Self::Delete),
+            "HEAD" => Ok(Self::Head),
+            "OPTIONS" => Ok(Self::Options),
+            _ => Err(HttpErrorKind::InvalidMethod),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FetchOptions {
+    method: HttpMethod,
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    max_redirects: u32,
+    retries: u32,
+    session_retries: u32,
+    retry_non_idempotent: bool,
+    retry_delay: Duration,
+    max_retry_wait: Duration,
+    max_body_size: Option<usize>,
+    read_buffer_size: usize,
+    total_timeout: Option<Duration>,
+    detect_truncation: bool,
+    expect_json: bool,
+    decode_chunked: bool,
+    query: Vec<(String, String)>,
+    user_agent: Option<String>,
+}
+
+impl FetchOptions {
+    /// Parses `method` case-insensitively. Unknown methods fall back to GET;
+    /// use [`FetchOptions::try_new`] or [`FetchOptions::with_method`] to avoid that.
+    pub fn new(method: &str) -> Self {
+        Self::with_method(method.parse().unwrap_or(HttpMethod::Get))
+    }
+
+    pub fn try_new(method: &str) -> Result<Self, HttpErrorKind> {
+        Ok(Self::with_method(method.parse()?))
+    }
+
+    pub fn builder() -> FetchOptionsBuilder {
+        FetchOptionsBuilder {
+            opts: FetchOptions::default(),
+        }
+    }
+
+    pub fn with_method(method: HttpMethod) -> Self {
+        FetchOptions {
+            method,
+            headers: HashMap::new(),
+            body: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            max_redirects: 0,
+            retries: 0,
+            session_retries: 0,
+            retry_non_idempotent: false,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            max_retry_wait: DEFAULT_MAX_RETRY_WAIT,
+            max_body_size: None,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            total_timeout: None,
+            detect_truncation: false,
+            expect_json: false,
+            decode_chunked: false,
+            query: Vec::new(),
+            user_agent: None,
+        }
+    }
+
+    /// Sets a header, replacing any existing value under the same name
+    /// regardless of case.
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers
+            .retain(|name, _| !name.eq_ignore_ascii_case(key));
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub(crate) fn has_header(&self, key: &str) -> bool {
+        self.headers
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case(key))
+    }
+
+    /// Overrides the default [`DEFAULT_USER_AGENT`] (and any `User-Agent`
+    /// header). An empty string sends no `User-Agent` at all.
+    pub fn user_agent(mut self, agent: &str) -> Self {
+        self.user_agent = Some(agent.into());
+        self
+    }
+
+    /// Percent-encodes `fields` into an `application/x-www-form-urlencoded`
+    /// body and sets the matching `Content-Type`.
+    pub fn form(self, fields: &[(&str, &str)]) -> Self {
+        self.header("Content-Type", "application/x-www-form-urlencoded")
+            .body(encode_pairs(fields.iter().copied()))
+    }
+
+    /// Sets the `Accept` header to `mime`.
+    pub fn accept(self, mime: &str) -> Self {
+        self.header("Accept", mime)
+    }
+
+    /// Shorthand for `accept("application/json")`, the natural pairing for
+    /// [`BlocklessHttp::get_json`].
+    pub fn accept_json(self) -> Self {
+        self.accept("application/json")
+    }
+
+    /// Makes the request conditional on `etag`, typically the `ETag` header of
+    /// an earlier response. An unchanged resource comes back as a 304; see
+    /// [`Response::not_modified`].
+    pub fn if_none_match(self, etag: &str) -> Self {
+        self.header("If-None-Match", etag)
+    }
+
+    /// Sets `Authorization: Bearer <token>`.
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Sets `Authorization: Basic <base64(user:pass)>`.
+    pub fn basic_auth(self, user: &str, pass: &str) -> Self {
+        let credentials = base64_encode(format!("{}:{}", user, pass).as_bytes());
+        self.header("Authorization", &format!("Basic {}", credentials))
+    }
+
+    /// Appends a percent-encoded `key=value` pair to the request URL's query.
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Appends every pair in order, which matters to APIs that sign the query.
+    pub fn queries(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.query
+            .extend(pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())));
+        self
+    }
+
+    /// Appends every pair in the map, sorted by key so the URL is stable.
+    pub fn queries_map(mut self, pairs: &HashMap<String, String>) -> Self {
+        let mut sorted: Vec<_> = pairs.iter().collect();
+        sorted.sort();
+        self.query
+            .extend(sorted.into_iter().map(|(k, v)| (k.clone(), v.clone())));
+        self
+    }
+
+    /// The host takes timeouts in whole seconds, so any fraction is rounded
+    /// up: 1500ms becomes 2s and 1ms becomes 1s. [`Duration::ZERO`] is passed
+    /// through as "no timeout".
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Rounded up to whole seconds like [`FetchOptions::connect_timeout`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Follows up to `max` redirects (301, 302, 303, 307, 308) using the
+    /// `Location` header. 303 switches to GET and drops the body; the others
+    /// keep the method. Revisiting a URL counts as too many redirects. Once a
+    /// redirect leaves the original scheme, host and port, `Authorization`,
+    /// `Cookie` and `Proxy-Authorization` are no longer sent.
+    pub fn follow_redirects(mut self, max: u32) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Retries a failed request up to `attempts` more times, waiting
+    /// `retry_delay` and doubling it after each try. Only errors for which
+    /// [`HttpErrorKind::is_retryable`] holds are retried, plus
+    /// `429 Too Many Requests` responses, which wait for the server's
+    /// `Retry-After` instead when it sends one. A `Retry-After` longer than
+    /// [`FetchOptions::max_retry_wait`] is not waited for: the `429` is
+    /// returned.
+    ///
+    /// Failed `POST` and `PATCH` requests are not retried unless
+    /// [`FetchOptions::retry_non_idempotent`] is set.
+    pub fn retry(mut self, attempts: u32) -> Self {
+        self.retries = attempts;
+        self
+    }
+
+    /// Lets [`FetchOptions::retry`] repeat methods that are not idempotent,
+    /// such as `POST` and `PATCH`.
+    ///
+    /// Off by default because a timeout or dropped connection does not tell
+    /// whether the server acted on the request: the response may have been
+    /// lost after the resource was created, and sending it again would
+    /// create a duplicate. Only enable this when the endpoint deduplicates,
+    /// e.g. through an idempotency key header. A `429` is still retried
+    /// either way, since the server has said it did not process the request.
+    pub fn retry_non_idempotent(mut self, enabled: bool) -> Self {
+        self.retry_non_idempotent = enabled;
+        self
+    }
+
+    /// Waits and retries up to `attempts` times when the host refuses the
+    /// request with [`HttpErrorKind::TooManySessions`], using the same
+    /// backoff as [`FetchOptions::retry`] and counted separately from it.
+    /// Sessions are only freed when their [`BlocklessHttp`] is closed or
+    /// dropped, so this helps only if other handles are released meanwhile,
+    /// e.g. by other tasks; handles held by the caller itself never free up.
+    pub fn retry_on_session_limit(mut self, attempts: u32) -> Self {
+        self.session_retries = attempts;
+        self
+    }
+
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// The longest `Retry-After` that [`FetchOptions::retry`] will sleep
+    /// for, [`DEFAULT_MAX_RETRY_WAIT`] unless set. Host calls block the
+    /// guest, so a server asking for more gets its `429` handed back instead.
+    pub fn max_retry_wait(mut self, wait: Duration) -> Self {
+        self.max_retry_wait = wait;
+        self
+    }
+
+    /// Fails body reads with [`HttpErrorKind::BodyTooLarge`] once more than
+    /// `bytes` have been received. This covers every way of reading the
+    /// body, streamed or whole, including [`BlocklessHttp::peek`]; whole-body
+    /// reads also fail up front when `Content-Length` is larger. Unbounded by
+    /// default.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.max_body_size = Some(bytes);
+        self
+    }
+
+    /// A deadline for the whole exchange, measured from when the request is
+    /// started: once it has passed, the next body read fails with
+    /// [`HttpErrorKind::Timeout`]. Unlike the read timeout this also stops a
+    /// body that trickles in slowly enough to never trip a single read. It
+    /// is checked between reads, so one slow read may overrun it.
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = Some(timeout);
+        self
+    }
+
+    /// Makes [`BlocklessHttp::get_all_body`] and [`BlocklessHttp::copy_to`]
+    /// fail with [`HttpErrorKind::TruncatedBody`] when the body ends before
+    /// the length advertised by `Content-Length`. Off by default.
+    pub fn detect_truncation(mut self, enabled: bool) -> Self {
+        self.detect_truncation = enabled;
+        self
+    }
+
+    /// Removes HTTP/1.1 chunk framing from responses sent with
+    /// `Transfer-Encoding: chunked`, for hosts that pass such bodies through
+    /// undecoded. Off by default: the `native` backend, like hosts built on
+    /// a regular HTTP client, already delivers the payload, and decoding it
+    /// again would corrupt it. When on, every body read returns the payload,
+    /// [`FetchOptions::max_body_size`] counts payload bytes, and framing that
+    /// is broken or ends early fails with `InvalidEncoding`.
+    pub fn decode_chunked(mut self, enabled: bool) -> Self {
+        self.decode_chunked = enabled;
+        self
+    }
+
+    /// Makes [`BlocklessHttp::get_json`] and [`fetch`] check that the
+    /// response is JSON (`application/json` or a `+json` type) before
+    /// reading it, failing with [`HttpErrorKind::UnexpectedContentType`]
+    /// otherwise. Catches HTML error pages served with a 200.
+    pub fn expect_json(mut self, enabled: bool) -> Self {
+        self.expect_json = enabled;
+        self
+    }
+
+    /// Size of each chunk requested from the host while reading the body.
+    pub fn read_buffer_size(mut self, bytes: usize) -> Self {
+        self.read_buffer_size = bytes.max(1);
+        self
+    }
+
+    #[cfg(any(feature = "test-util", feature = "record"))]
+    pub(crate) fn query_pairs(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    pub(crate) fn http_options(&self) -> HttpOptions {
+        let mut http_opts = HttpOptions::new(
+            self.method.as_str(),
+            timeout_secs(self.connect_timeout),
+            timeout_secs(self.read_timeout),
+        );
+        http_opts.headers = self.headers.clone();
+        for (name, value) in default_headers().lock().unwrap().iter() {
+            if !self.has_header(name) {
+                http_opts.headers.insert(name.clone(), value.clone());
+            }
+        }
+        http_opts.body = self.body.clone();
+        match &self.user_agent {
+            Some(agent) => {
+                http_opts
+                    .headers
+                    .retain(|name, _| !name.eq_ignore_ascii_case("user-agent"));
+                if !agent.is_empty() {
+                    http_opts.headers.insert("User-Agent".into(), agent.clone());
+                }
+            }
+            None => {
+                let has_agent = http_opts
+                    .headers
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case("user-agent"));
+                if !has_agent {
+                    http_opts
+                        .headers
+                        .insert("User-Agent".into(), DEFAULT_USER_AGENT.into());
+                }
+            }
+        }
+        http_opts
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+
+    #[deprecated(note = "use `to_json_string`")]
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> String {
+        self.to_json_string()
+    }
+}
+
+/// A GET with no headers or body and the default timeouts.
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self::with_method(HttpMethod::Get)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchOptionsBuilder {
+    opts: FetchOptions,
+}
+
+impl FetchOptionsBuilder {
+    pub fn method(mut self, method: HttpMethod) -> Self {
+        self.opts.method = method;
+        self
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.opts = self.opts.header(key, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.opts = self.opts.body(body);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.opts = self.opts.connect_timeout(timeout);
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.opts = self.opts.read_timeout(timeout);
+        self
+    }
+
+    pub fn build(self) -> FetchOptions {
+        self.opts
+    }
+}
+
+/// Sent as `User-Agent` unless the request sets its own.
+pub const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 8192;
+
+const MAX_PREALLOCATION: usize = 16 * 1024 * 1024;
+
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+pub const DEFAULT_MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// How many times a read is retried while the host reports it is not ready
+/// (`u32::MAX`) before giving up with [`HttpErrorKind::WouldBlock`].
+pub const MAX_READ_ATTEMPTS: u32 = 10_000;
+
+const READ_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+// Handles currently owned by a `BlocklessHttp`, and the optional cap on them.
+static OPEN_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+static SESSION_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+fn default_headers() -> &'static Mutex<HashMap<String, String>> {
+    static DEFAULT_HEADERS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    DEFAULT_HEADERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Replaces the process-wide headers added to every request, including
+/// those made with [`get`], [`post`] and a [`crate::client::Client`]. A
+/// header the request sets itself, under any casing, wins over the default.
+/// A default `User-Agent` replaces [`DEFAULT_USER_AGENT`], while
+/// [`FetchOptions::user_agent`] still overrides both.
+pub fn set_default_headers(headers: HashMap<String, String>) {
+    *default_headers().lock().unwrap() = headers;
+}
+
+/// Number of handles currently held open by [`BlocklessHttp`] values in this
+/// process. A handle counts from when it is opened (or adopted with
+/// [`BlocklessHttp::from_handle`]) until it is closed or dropped, including
+/// drops during a panic, so a count that keeps growing points at values
+/// that are being kept alive.
+pub fn open_session_count() -> usize {
+    OPEN_SESSIONS.load(AtomicOrdering::Relaxed)
+}
+
+thread_local! {
+    // Return code of the latest host call that went through `check`.
+    static LAST_HOST_CODE: Cell<u32> = const { Cell::new(0) };
+}
+
+/// The raw return code of the latest `blockless_http` call on this thread:
+/// `None` if it succeeded, otherwise the code the host returned, which its
+/// [`HttpErrorKind`] was converted from (`u32::MAX` when retries for "not
+/// ready" ran out). Errors this crate raises without calling the host, such
+/// as `InvalidUrl` from URL validation, `InvalidEncoding` from decoding a
+/// body or the session limit's `TooManySessions`, leave it untouched, so
+/// `None` right after an error means the host did not report it. So do
+/// header lookups the crate makes on its own behalf, such as the
+/// [`KNOWN_HEADERS`] sweep of [`BlocklessHttp::get_all_headers`], when the
+/// header is simply absent.
+pub fn last_host_code() -> Option<u32> {
+    match LAST_HOST_CODE.with(Cell::get) {
+        0 => None,
+        code => Some(code),
+    }
+}
+
+/// Makes requests fail up front with [`HttpErrorKind::TooManySessions`]
+/// while `limit` handles are already open, rather than leaving it to the
+/// host. Set it to the host's own limit for a clearer error, or lower to
+/// keep headroom. `0`, the default, disables the check. The check is not
+/// atomic with opening, so concurrent requests may overshoot it slightly.
+pub fn set_session_limit(limit: usize) {
+    SESSION_LIMIT.store(limit, AtomicOrdering::Relaxed);
+}
+
+/// Response headers probed by [`BlocklessHttp::get_all_headers`].
+pub const KNOWN_HEADERS: &[&str] = &[
+    "accept-ranges",
+    "access-control-allow-credentials",
+    "access-control-allow-headers",
+    "access-control-allow-methods",
+    "access-control-allow-origin",
+    "access-control-expose-headers",
+    "access-control-max-age",
+    "age",
+    "allow",
+    "cache-control",
+    "connection",
+    "content-disposition",
+    "content-encoding",
+    "content-language",
+    "content-length",
+    "content-location",
+    "content-range",
+    "content-security-policy",
+    "content-type",
+    "date",
+    "etag",
+    "expires",
+    "keep-alive",
+    "last-modified",
+    "link",
+    "location",
+    "pragma",
+    "retry-after",
+    "server",
+    "set-cookie",
+    "strict-transport-security",
+    "transfer-encoding",
+    "vary",
+    "via",
+    "www-authenticate",
+    "x-content-type-options",
+    "x-frame-options",
+    "x-ratelimit-limit",
+    "x-ratelimit-remaining",
+    "x-ratelimit-reset",
+    "x-request-id",
+];
+
+/// A `blockless_http` session handle.
+pub type Handle = u32;
+
+/// The HTTP status code of a response (200, 404, ...). Not to be confused
+/// with the return code of a host call, which is `0` on success and
+/// otherwise becomes an [`HttpErrorKind`]; see [`last_host_code`].
+pub type CodeStatus = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusCategory {
+    Informational,
+    Success,
+    Redirection,
+    ClientError,
+    ServerError,
+    Unknown,
+}
+
+impl From<CodeStatus> for StatusCategory {
+    fn from(code: CodeStatus) -> StatusCategory {
+        match code {
+            100..=199 => StatusCategory::Informational,
+            200..=299 => StatusCategory::Success,
+            300..=399 => StatusCategory::Redirection,
+            400..=499 => StatusCategory::ClientError,
+            500..=599 => StatusCategory::ServerError,
+            _ => StatusCategory::Unknown,
+        }
+    }
+}
+
+/// An HTTP status code. Host codes that do not fit in a `u16` (never valid
+/// HTTP) become `0`, which belongs to no category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+impl StatusCode {
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    pub fn category(&self) -> StatusCategory {
+        StatusCategory::from(self.0 as CodeStatus)
+    }
+
+    pub fn is_informational(&self) -> bool {
+        self.category() == StatusCategory::Informational
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.category() == StatusCategory::Success
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        self.category() == StatusCategory::Redirection
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        self.category() == StatusCategory::ClientError
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        self.category() == StatusCategory::ServerError
+    }
+
+    /// The standard reason phrase, e.g. `"Not Found"` for 404.
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        let reason = match self.0 {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            102 => "Processing",
+            103 => "Early Hints",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            203 => "Non-Authoritative Information",
+            204 => "No Content",
+            205 => "Reset Content",
+            206 => "Partial Content",
+            207 => "Multi-Status",
+            208 => "Already Reported",
+            226 => "IM Used",
+            300 => "Multiple Choices",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            305 => "Use Proxy",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            402 => "Payment Required",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            407 => "Proxy Authentication Required",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Payload Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            417 => "Expectation Failed",
+            418 => "I'm a teapot",
+            421 => "Misdirected Request",
+            422 => "Unprocessable Entity",
+            423 => "Locked",
+            424 => "Failed Dependency",
+            425 => "Too Early",
+            426 => "Upgrade Required",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            451 => "Unavailable For Legal Reasons",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            506 => "Variant Also Negotiates",
+            507 => "Insufficient Storage",
+            508 => "Loop Detected",
+            510 => "Not Extended",
+            511 => "Network Authentication Required",
+            _ => return None,
+        };
+        Some(reason)
+    }
+}
+
+impl From<u16> for StatusCode {
+    fn from(code: u16) -> StatusCode {
+        StatusCode(code)
+    }
+}
+
+impl From<CodeStatus> for StatusCode {
+    fn from(code: CodeStatus) -> StatusCode {
+        StatusCode(u16::try_from(code).unwrap_or(0))
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(status: StatusCode) -> u16 {
+        status.0
+    }
+}
+
+impl std::fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// A response's status line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusLine {
+    /// The HTTP version, e.g. `HTTP/1.1`. The host does not report it, so
+    /// this is `None` for responses read through `blockless_http`.
+    pub version: Option<String>,
+    pub code: u16,
+    /// The host does not pass on the server's reason phrase either, so this
+    /// is the canonical one for `code`, or empty for codes without one.
+    pub reason: String,
+}
+
+impl StatusLine {
+    fn from_code(code: CodeStatus) -> Self {
+        let status = StatusCode::from(code);
+        StatusLine {
+            version: None,
+            code: status.as_u16(),
+            reason: status.canonical_reason().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for StatusLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(version) = &self.version {
+            write!(f, "{} ", version)?;
+        }
+        write!(f, "{}", self.code)?;
+        if !self.reason.is_empty() {
+            write!(f, " {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct BlocklessHttp {
+    inner: Handle,
+    code: CodeStatus,
+    // The URL this handle was opened for; empty for adopted handles.
+    url: String,
+    max_body_size: Option<usize>,
+    read_buffer_size: usize,
+    // Responses to HEAD have no body, so reads end without asking the host.
+    head_request: bool,
+    // Seconds; used to tell a read timeout apart from other failed reads.
+    read_timeout: u32,
+    total_timeout: Option<Duration>,
+    detect_truncation: bool,
+    expect_json: bool,
+    // Set when `decode_chunked` applies: strips the framing from host reads.
+    chunks: Option<RefCell<ChunkDecoder>>,
+    // Body bytes read by `peek` and not yet handed out.
+    peeked: RefCell<Vec<u8>>,
+    // Body bytes received so far, checked against `max_body_size`.
+    received: Cell<u64>,
+    started: Instant,
+    // Set once the host handle is closed ahead of drop, by `reopen`.
+    released: bool,
+}
+
+/// The request description handed to the host by `http_open`. Most callers
+/// want [`FetchOptions`]; this is for full control via [`BlocklessHttp::open_with`].
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
     method: String,
+    connect_timeout: u32,
+    read_timeout: u32,
+    headers: HashMap<String, String>,
+    body: Option<String>,
+}
+
+impl HttpOptions {
+    pub fn new(method: &str, connect_timeout: u32, read_timeout: u32) -> Self {
+        HttpOptions {
+            method: method.into(),
+            connect_timeout,
+            read_timeout,
+            headers: HashMap::new(),
+            body: None,
+        }
+    }
+
+    pub fn method(mut self, method: &str) -> Self {
+        self.method = method.into();
+        self
+    }
+
+    pub fn connect_timeout(mut self, secs: u32) -> Self {
+        self.connect_timeout = secs;
+        self
+    }
+
+    pub fn read_timeout(mut self, secs: u32) -> Self {
+        self.read_timeout = secs;
+        self
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "method": self.method,
+            "connectTimeout": self.connect_timeout,
+            "readTimeout": self.read_timeout,
+            "headers": self.headers,
+            "body": self.body,
+        })
+    }
+}
+
+/// What [`BlocklessHttp::open_detailed`] returns: the open handle together
+/// with the metadata most callers check before reading the body.
+pub struct OpenResult {
+    pub http: BlocklessHttp,
+    pub status: u16,
+    /// The parsed `Content-Length`; `None` if absent or malformed.
+    pub content_length: Option<u64>,
+}
+
+impl BlocklessHttp {
+    /// [`BlocklessHttp::open`] for options built inline and not reused.
+    pub fn open_owned(url: &str, opts: FetchOptions) -> Result<Self, HttpErrorKind> {
+        Self::open(url, &opts)
+    }
+
+    /// [`BlocklessHttp::open`] followed by [`BlocklessHttp::error_for_status`]:
+    /// a non-2xx response is `HttpStatusError` and its handle is released.
+    pub fn open_ok(url: &str, opts: &FetchOptions) -> Result<Self, HttpErrorKind> {
+        Self::open(url, opts)?.error_for_status()
+    }
+
+    /// [`BlocklessHttp::open`] that also reads `Content-Length` while the
+    /// response is opened, for the "check the size, then read" pattern.
+    /// The host reports headers only on request, so this is the same one
+    /// header read as [`BlocklessHttp::content_length`], made up front so
+    /// the caller need not go back to the handle for it.
+    pub fn open_detailed(url: &str, opts: &FetchOptions) -> Result<OpenResult, HttpErrorKind> {
+        let http = Self::open(url, opts)?;
+        Ok(OpenResult {
+            status: http.status().as_u16(),
+            content_length: http.content_length(),
+            http,
+        })
+    }
+
+    pub fn open(url: &str, opts: &FetchOptions) -> Result<Self, HttpErrorKind> {
+        let mut http_opts = opts.http_options();
+        let mut url = append_query(url, &opts.query);
+        let mut visited = HashSet::new();
+        let started = Instant::now();
+        loop {
+            let mut http = Self::open_retrying(&url, &http_opts, opts)?;
+            http.configure(opts);
+            http.started = started;
+            if opts.max_redirects == 0 || !is_redirect(http.code) {
+                return Ok(http);
+            }
+            let Some(location) = http.find_header("Location")? else {
+                return Ok(http);
+            };
+            visited.insert(url.clone());
+            let next = resolve_location(&url, location.trim());
+            if visited.len() > opts.max_redirects as usize || visited.contains(&next) {
+                return Err(HttpErrorKind::TooManyRedirects);
+            }
+            if origin(&url) != origin(&next) {
+                http_opts
+                    .headers
+                    .retain(|name, _| !is_credential_header(name));
+            }
+            if http.code == 303 && http_opts.method != HttpMethod::Head.as_str() {
+                http_opts.method = HttpMethod::Get.as_str().into();
+                http_opts.body = None;
+            }
+            url = next;
+        }
+    }
+
+    fn configure(&mut self, opts: &FetchOptions) {
+        self.max_body_size = opts.max_body_size;
+        self.read_buffer_size = opts.read_buffer_size;
+        self.total_timeout = opts.total_timeout;
+        self.detect_truncation = opts.detect_truncation;
+        self.expect_json = opts.expect_json;
+        self.chunks = (opts.decode_chunked && self.is_chunked())
+            .then(|| RefCell::new(ChunkDecoder::default()));
+    }
+
+    fn open_retrying(
+        url: &str,
+        http_opts: &HttpOptions,
+        opts: &FetchOptions,
+    ) -> Result<Self, HttpErrorKind> {
+        let backoff = |attempt: u32| {
+            let factor = 2u32.saturating_pow(attempt);
+            std::thread::sleep(opts.retry_delay.saturating_mul(factor));
+        };
+        let resend = opts.method.is_idempotent() || opts.retry_non_idempotent;
+        let mut attempt = 0;
+        let mut session_attempt = 0;
+        loop {
+            match Self::open_with(url, http_opts) {
+                Err(HttpErrorKind::TooManySessions) if session_attempt < opts.session_retries => {
+                    backoff(session_attempt);
+                    session_attempt += 1;
+                }
+                Err(e) if resend && e.is_retryable() && attempt < opts.retries => {
+                    backoff(attempt);
+                    attempt += 1;
+                }
+                Ok(http) if http.code == 429 && attempt < opts.retries => {
+                    let wait = http.retry_after();
+                    if wait.is_some_and(|wait| wait > opts.max_retry_wait) {
+                        return Ok(http);
+                    }
+                    drop(http);
+                    match wait {
+                        Some(wait) => std::thread::sleep(wait),
+                        None => backoff(attempt),
+                    }
+                    attempt += 1;
+                }
+                rs => return rs,
+            }
+        }
+    }
+
+    /// Issues exactly one request described by `http_opts`, without the
+    /// redirect, retry and body-limit handling [`BlocklessHttp::open`] layers on.
+    pub fn open_with(url: &str, http_opts: &HttpOptions) -> Result<Self, HttpErrorKind> {
+        let url = &normalize_url(url)?;
+        let limit = SESSION_LIMIT.load(AtomicOrdering::Relaxed);
+        if limit > 0 && open_session_count() >= limit {
+            log_warn!(
+                "{} {} refused: {} sessions open",
+                http_opts.method,
+                url,
+                limit
+            );
+            return Err(HttpErrorKind::TooManySessions);
+        }
+        let http_opts_str = serde_json::to_string(&http_opts.to_json()).unwrap();
+        let started = Instant::now();
+
+        let mut fd = 0;
+        let mut status = 0;
+        let rs = unsafe {
+            http_open(
+                url.as_ptr(),
+                url.len() as _,
+                http_opts_str.as_ptr(),
+                http_opts_str.len() as _,
+                &mut fd,
+                &mut status,
+            )
+        };
+        if let Err(e) = HttpErrorKind::check(rs) {
+            let elapsed = started.elapsed();
+            let e = match open_timeout(elapsed, http_opts.connect_timeout, http_opts.read_timeout) {
+                Some(timeout) => e.timed_out(timeout),
+                None => e,
+            };
+            log_warn!("{} {} failed: {}", http_opts.method, url, e);
+            return Err(e);
+        }
+        log_debug!("{} {} -> {}", http_opts.method, url, status);
+        let mut http = unsafe { Self::from_handle(fd, status) };
+        http.head_request = http_opts
+            .method
+            .eq_ignore_ascii_case(HttpMethod::Head.as_str());
+        http.read_timeout = http_opts.read_timeout;
+        http.started = started;
+        http.url = url.clone();
+        Ok(http)
+    }
+
+    /// Adopts a handle obtained from the host outside of [`BlocklessHttp::open`].
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be a live `blockless_http` handle that nothing else will use
+    /// or close: the returned value takes ownership and closes it on drop.
+    pub unsafe fn from_handle(fd: Handle, code: CodeStatus) -> Self {
+        OPEN_SESSIONS.fetch_add(1, AtomicOrdering::Relaxed);
+        Self {
+            inner: fd,
+            code,
+            url: String::new(),
+            max_body_size: None,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            head_request: false,
+            read_timeout: 0,
+            total_timeout: None,
+            detect_truncation: false,
+            expect_json: false,
+            chunks: None,
+            peeked: RefCell::new(Vec::new()),
+            received: Cell::new(0),
+            started: Instant::now(),
+            released: false,
+        }
+    }
+
+    pub fn get_code(&self) -> CodeStatus {
+        self.code
+    }
+
+    /// The URL that produced this response: after [`BlocklessHttp::open`]
+    /// follows redirects, the last one fetched. Empty for handles adopted
+    /// through [`BlocklessHttp::from_handle`].
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from(self.code)
+    }
+
+    pub fn status_line(&self) -> StatusLine {
+        StatusLine::from_code(self.code)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status_category() == StatusCategory::Success
+    }
+
+    /// How long the server asks us to wait before trying again, from a
+    /// `Retry-After` header in either delay-seconds or HTTP-date form. A date
+    /// in the past gives [`Duration::ZERO`].
+    pub fn retry_after(&self) -> Option<Duration> {
+        parse_retry_after(&self.find_header("Retry-After").ok()??)
+    }
+
+    /// Passes a 2xx response through and turns any other status into
+    /// [`HttpErrorKind::HttpStatusError`], releasing the handle.
+    pub fn error_for_status(self) -> Result<Self, HttpErrorKind> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(HttpErrorKind::HttpStatusError(self.status().as_u16()))
+        }
+    }
+
+    /// Time since the request was started, including any retries and
+    /// redirects. Relies on the WASI monotonic clock on wasm32.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn status_category(&self) -> StatusCategory {
+        StatusCategory::from(self.code)
+    }
+
+    pub fn get_all_body(&self) -> Result<Vec<u8>, HttpErrorKind> {
+        self.read_all_body(self.max_body_size)
+    }
+
+    /// Clears `buf` and fills it with the whole body, returning its length.
+    /// Reusing one buffer across responses saves allocating a fresh `Vec`
+    /// each time; the body is read straight into it, with no scratch buffer.
+    /// Limits apply as for [`BlocklessHttp::get_all_body`].
+    pub fn read_all_into(&self, buf: &mut Vec<u8>) -> Result<usize, HttpErrorKind> {
+        buf.clear();
+        self.fill(self.max_body_size, buf)
+    }
+
+    fn read_all_body(&self, limit: Option<usize>) -> Result<Vec<u8>, HttpErrorKind> {
+        let mut vec = Vec::new();
+        self.fill(limit, &mut vec)?;
+        Ok(vec)
+    }
+
+    fn fill(&self, limit: Option<usize>, vec: &mut Vec<u8>) -> Result<usize, HttpErrorKind> {
+        let rs = self.read_to_limit(limit, vec);
+        match &rs {
+            Ok(len) => log_debug!("handle {}: read {} body bytes", self.inner, len),
+            Err(e) => log_warn!("handle {}: reading body failed: {}", self.inner, e),
+        }
+        rs
+    }
+
+    // Appends the body to the empty `vec`.
+    fn read_to_limit(
+        &self,
+        limit: Option<usize>,
+        vec: &mut Vec<u8>,
+    ) -> Result<usize, HttpErrorKind> {
+        if self.head_request {
+            return Ok(0);
+        }
+        // An advertised length over the limit fails before anything is read.
+        if let (Some(max), Some(len)) = (limit, self.content_length()) {
+            if len > max as u64 {
+                return Err(HttpErrorKind::BodyTooLarge);
+            }
+        }
+        vec.reserve(self.expected_body_len(limit));
+        loop {
+            let start = vec.len();
+            vec.resize(start + self.read_buffer_size, 0);
+            let num = match self.read_chunk(&mut vec[start..]) {
+                Ok(num) => num,
+                Err(e) => {
+                    vec.truncate(start);
+                    return Err(e);
+                }
+            };
+            vec.truncate(start + num);
+            if num == 0 {
+                break;
+            }
+            if limit.is_some_and(|max| vec.len() > max) {
+                return Err(HttpErrorKind::BodyTooLarge);
+            }
+        }
+        self.check_complete(vec.len() as u64)?;
+        Ok(vec.len())
+    }
+
+    /// Yields the body in chunks of up to the configured read buffer size,
+    /// ending after the host signals end of body.
+    pub fn body_chunks(&self) -> impl Iterator<Item = Result<Vec<u8>, HttpErrorKind>> + '_ {
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let mut buf = vec![0u8; self.read_buffer_size];
+            match self.read_chunk(&mut buf) {
+                Ok(0) => {
+                    done = true;
+                    None
+                }
+                Ok(num) => {
+                    buf.truncate(num);
+                    Some(Ok(buf))
+                }
+                Err(e) => {
+                    done = true;
+                    Some(Err(e))
+                }
+            }
+        })
+    }
+
+    /// Streams the body into `out`, calling `progress` with the running total
+    /// after each chunk. Returns the number of bytes written.
+    pub fn copy_to<W: std::io::Write>(
+        &self,
+        out: &mut W,
+        mut progress: impl FnMut(u64),
+    ) -> Result<u64, HttpErrorKind> {
+        let mut total: u64 = 0;
+        for chunk in self.body_chunks() {
+            let chunk = chunk?;
+            total += chunk.len() as u64;
+            out.write_all(&chunk)
+                .map_err(|e| HttpErrorKind::Io(e.kind()))?;
+            progress(total);
+        }
+        self.check_complete(total)?;
+        out.flush().map_err(|e| HttpErrorKind::Io(e.kind()))?;
+        Ok(total)
+    }
+
+    fn check_json(&self) -> Result<(), HttpErrorKind> {
+        if !self.expect_json {
+            return Ok(());
+        }
+        let content_type = self.find_header("Content-Type")?.unwrap_or_default();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        let media_type = media_type.to_ascii_lowercase();
+        if media_type == "application/json" || media_type.ends_with("+json") {
+            Ok(())
+        } else {
+            Err(HttpErrorKind::UnexpectedContentType(content_type))
+        }
+    }
+
+    // Whether `chunked` is the last transfer coding, i.e. the body is framed.
+    fn is_chunked(&self) -> bool {
+        let codings = self.find_headers("Transfer-Encoding").ok().flatten();
+        let codings = codings.unwrap_or_default();
+        codings
+            .last()
+            .and_then(|value| value.rsplit(',').next())
+            .is_some_and(|last| last.trim().eq_ignore_ascii_case("chunked"))
+    }
+
+    fn check_complete(&self, read: u64) -> Result<(), HttpErrorKind> {
+        if !self.detect_truncation {
+            return Ok(());
+        }
+        match self.content_length() {
+            Some(expected) if read < expected => Err(HttpErrorKind::TruncatedBody),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns up to the first `n` bytes of the body without consuming them:
+    /// later reads, whole-body or streamed, start from the same place. Fewer
+    /// than `n` bytes are returned only if the body is shorter. Handy for
+    /// sniffing the content, e.g. `{` versus `<`, before choosing a parser.
+    pub fn peek(&mut self, n: usize) -> Result<&[u8], HttpErrorKind> {
+        let mut buf = vec![0u8; self.read_buffer_size];
+        while self.peeked.get_mut().len() < n {
+            let num = self.read_from_host(&mut buf)?;
+            if num == 0 {
+                break;
+            }
+            self.peeked.get_mut().extend_from_slice(&buf[..num]);
+        }
+        let peeked = self.peeked.get_mut();
+        Ok(&peeked[..n.min(peeked.len())])
+    }
+
+    // One body read, serving peeked bytes before asking the host. `Ok(0)` is EOF.
+    fn read_chunk(&self, buf: &mut [u8]) -> Result<usize, HttpErrorKind> {
+        let mut peeked = self.peeked.borrow_mut();
+        if !peeked.is_empty() {
+            let n = buf.len().min(peeked.len());
+            buf[..n].copy_from_slice(&peeked[..n]);
+            peeked.drain(..n);
+            return Ok(n);
+        }
+        drop(peeked);
+        self.read_from_host(buf)
+    }
+
+    // One read from the host, counted against `max_body_size`.
+    fn read_from_host(&self, buf: &mut [u8]) -> Result<usize, HttpErrorKind> {
+        let num = self.read_payload(buf)?;
+        let received = self.received.get() + num as u64;
+        self.received.set(received);
+        if self.max_body_size.is_some_and(|max| received > max as u64) {
+            return Err(HttpErrorKind::BodyTooLarge);
+        }
+        Ok(num)
+    }
+
+    // One read from the host, with the chunk framing removed when
+    // `FetchOptions::decode_chunked` applies. `Ok(0)` is EOF.
+    fn read_payload(&self, buf: &mut [u8]) -> Result<usize, HttpErrorKind> {
+        let Some(chunks) = &self.chunks else {
+            return self.read_raw(buf);
+        };
+        let mut chunks = chunks.borrow_mut();
+        loop {
+            let n = chunks.take(buf);
+            if n > 0 || chunks.is_done() {
+                return Ok(n);
+            }
+            match self.read_raw(buf)? {
+                0 => return chunks.finish().map(|()| 0),
+                num => chunks.feed(&buf[..num])?,
+            }
+        }
+    }
+
+    // One host read, waiting out the not-ready sentinel.
+    fn read_raw(&self, buf: &mut [u8]) -> Result<usize, HttpErrorKind> {
+        if self.head_request {
+            return Ok(0);
+        }
+        if self
+            .total_timeout
+            .is_some_and(|timeout| self.started.elapsed() >= timeout)
+        {
+            return Err(HttpErrorKind::Timeout);
+        }
+        if self.released {
+            return Err(HttpErrorKind::InvalidHandle);
+        }
+        let mut num: u32 = 0;
+        let started = Instant::now();
+        retry_pending(|| unsafe {
+            http_read_body(self.inner, buf.as_mut_ptr(), buf.len() as _, &mut num)
+        })
+        .map_err(|e| {
+            if exceeded(started.elapsed(), self.read_timeout) {
+                e.timed_out(HttpErrorKind::ReadTimeout)
+            } else {
+                e
+            }
+        })?;
+        Ok(num as usize)
+    }
+
+    // Capacity to reserve up front: the advertised Content-Length, but never
+    // more than we would accept or than an untrusted header should make us
+    // allocate in one go.
+    fn expected_body_len(&self, limit: Option<usize>) -> usize {
+        let advertised = self.content_length().unwrap_or(0);
+        let cap = limit.unwrap_or(MAX_PREALLOCATION);
+        usize::try_from(advertised)
+            .unwrap_or(usize::MAX)
+            .min(cap.min(MAX_PREALLOCATION))
+    }
+
+    /// Decodes the body using the `charset` from `Content-Type`: UTF-8 when
+    /// absent, ISO-8859-1 when declared, and `InvalidEncoding` for charsets
+    /// that are not supported.
+    pub fn text(&self) -> Result<String, HttpErrorKind> {
+        decode_text(
+            self.get_all_body()?,
+            self.find_header("Content-Type").ok().flatten().as_deref(),
+        )
+    }
+
+    /// Reads at most `max` bytes (less if [`FetchOptions::max_body_size`] is
+    /// smaller) and decodes them as [`BlocklessHttp::text`] does. A longer body, or one whose
+    /// `Content-Length` says it will be longer, is `BodyTooLarge`.
+    pub fn text_with_limit(&self, max: usize) -> Result<String, HttpErrorKind> {
+        let limit = self
+            .max_body_size
+            .map_or(max, |configured| configured.min(max));
+        let body = self.read_all_body(Some(limit))?;
+        let content_type = self.find_header("Content-Type").ok().flatten();
+        decode_text(body, content_type.as_deref())
+    }
+
+    /// Like [`BlocklessHttp::text`], but invalid UTF-8 is replaced with U+FFFD.
+    pub fn text_lossy(&self) -> Result<String, HttpErrorKind> {
+        Ok(String::from_utf8_lossy(strip_bom(&self.get_all_body()?)).into_owned())
+    }
+
+    /// Reads the whole body and inflates it according to `Content-Encoding`
+    /// (`gzip` or `deflate`). Bodies without an encoding are returned as-is.
+    /// [`FetchOptions::max_body_size`] limits both the compressed body and
+    /// the inflated result, so a small body that inflates past the limit is
+    /// `BodyTooLarge` rather than exhausting memory.
+    #[cfg(feature = "gzip")]
+    pub fn get_all_body_decoded(&self) -> Result<Vec<u8>, HttpErrorKind> {
+        use std::io::Read;
+
+        let body = self.get_all_body()?;
+        let Some(encoding) = self.find_header("Content-Encoding")? else {
+            return Ok(body);
+        };
+        let encoding = encoding.trim().to_ascii_lowercase();
+        let decoder: Box<dyn Read + '_> = match encoding.as_str() {
+            "" | "identity" => return Ok(body),
+            "gzip" | "x-gzip" => Box::new(flate2::read::GzDecoder::new(&body[..])),
+            "deflate" => Box::new(flate2::read::ZlibDecoder::new(&body[..])),
+            _ => return Err(HttpErrorKind::InvalidEncoding),
+        };
+        // One byte past the limit is enough to tell that it was exceeded.
+        let cap = self.max_body_size.map_or(u64::MAX, |max| max as u64 + 1);
+        let mut decoded = Vec::new();
+        decoder
+            .take(cap)
+            .read_to_end(&mut decoded)
+            .map_err(|_| HttpErrorKind::InvalidEncoding)?;
+        if self.max_body_size.is_some_and(|max| decoded.len() > max) {
+            return Err(HttpErrorKind::BodyTooLarge);
+        }
+        Ok(decoded)
+    }
+
+    /// Parses the body as `T`, whatever the status, so JSON error bodies can
+    /// be read too. Use [`BlocklessHttp::error_for_status`] first to turn a
+    /// non-2xx response into an error instead.
+    pub fn get_json<T: DeserializeOwned>(&self) -> Result<T, FetchError> {
+        self.check_json()?;
+        let body = self.get_all_body()?;
+        Ok(serde_json::from_slice(strip_bom(&body))?)
+    }
+
+    /// Parses the body and returns the value at the RFC 6901 pointer `ptr`,
+    /// e.g. `/ethereum/usd`, or `None` if nothing is there.
+    pub fn get_json_pointer(&self, ptr: &str) -> Result<Option<Value>, FetchError> {
+        let mut value: Value = self.get_json()?;
+        Ok(value.pointer_mut(ptr).map(Value::take))
+    }
+
+    /// Parses the body as the API's success shape `T`, falling back to its
+    /// error shape `E`. A body that is neither is [`FetchError::Parse`]
+    /// describing why it did not match `T`.
+    pub fn get_json_or_error<T, E>(&self) -> Result<Result<T, E>, FetchError>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned,
+    {
+        json_or_error(&self.get_all_body()?)
+    }
+
+    /// Header names are case-insensitive: the name is lowercased before it is
+    /// handed to the host, so `Content-Type` and `content-type` are the same
+    /// lookup regardless of how the host matches names. For a header the
+    /// server sent more than once this is the first value; see
+    /// [`BlocklessHttp::get_headers`].
+    pub fn get_header(&self, header: &str) -> Result<String, HttpErrorKind> {
+        let raw = self.read_header(header)?;
+        let first = header_values(&raw).next().unwrap_or_default();
+        Ok(first.to_string())
+    }
+
+    /// Every value of a header the server may send more than once, such as
+    /// `Set-Cookie`, taken from the value the host returns, one per line.
+    ///
+    /// The `blockless_http` ABI only specifies that `http_read_header`
+    /// returns one value per name; it does not say how a repeated header is
+    /// represented. The `native` backend and canned [`Response`]s put each
+    /// occurrence on its own line. On the wasm host this splits whatever
+    /// `http_read_header` returns, so a host that keeps only one occurrence,
+    /// or joins them with `, `, yields a single value.
+    pub fn get_headers(&self, header: &str) -> Result<Vec<String>, HttpErrorKind> {
+        let raw = self.read_header(header)?;
+        Ok(header_values(&raw).map(String::from).collect())
+    }
+
+    // `get_header` for lookups the crate makes itself: an absent header is
+    // `None`, and leaves `last_host_code` as it was.
+    pub(crate) fn find_header(&self, header: &str) -> Result<Option<String>, HttpErrorKind> {
+        Ok(self
+            .find_headers(header)?
+            .map(|values| values.into_iter().next().unwrap_or_default()))
+    }
+
+    pub(crate) fn find_headers(&self, header: &str) -> Result<Option<Vec<String>>, HttpErrorKind> {
+        let code = LAST_HOST_CODE.with(Cell::get);
+        match self.get_headers(header) {
+            Ok(values) => Ok(Some(values)),
+            Err(HttpErrorKind::HeaderNotFound) => {
+                LAST_HOST_CODE.with(|last| last.set(code));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_header(&self, header: &str) -> Result<String, HttpErrorKind> {
+        if self.released {
+            return Err(HttpErrorKind::InvalidHandle);
+        }
+        let header = header.to_ascii_lowercase();
+        let mut vec = Vec::new();
+        loop {
+            let mut buf = [0u8; 1024];
+            let mut num: u32 = 0;
+            retry_pending(|| unsafe {
+                http_read_header(
+                    self.inner,
+                    header.as_ptr(),
+                    header.len() as _,
+                    buf.as_mut_ptr(),
+                    buf.len() as _,
+                    &mut num,
+                )
+            })?;
+
+            match num.cmp(&0) {
+                Ordering::Greater => vec.extend_from_slice(&buf[0..num as _]),
+                _ => break,
+            }
+        }
+        String::from_utf8(vec).map_err(|_| HttpErrorKind::Utf8Error)
+    }
+
+    /// Parses the `Content-Length` response header; `None` if absent or malformed.
+    pub fn content_length(&self) -> Option<u64> {
+        self.find_header("Content-Length")
+            .ok()??
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// The host has no call to enumerate response headers, so this probes each
+    /// name in [`KNOWN_HEADERS`] and keeps the ones the server sent. Keys are
+    /// lowercase. A header returned as several lines, as the `native` backend
+    /// does for one sent more than once, keeps every line, joined with `\n`;
+    /// [`BlocklessHttp::get_headers`] describes what the wasm host provides.
+    pub fn get_all_headers(&self) -> Result<HashMap<String, String>, HttpErrorKind> {
+        let mut headers = HashMap::new();
+        for name in KNOWN_HEADERS {
+            if let Some(values) = self.find_headers(name)? {
+                let values: Vec<_> = values.into_iter().filter(|v| !v.is_empty()).collect();
+                if !values.is_empty() {
+                    headers.insert(name.to_string(), values.join("\n"));
+                }
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Lazily yields the headers [`BlocklessHttp::get_all_headers`] would
+    /// find, in [`KNOWN_HEADERS`] order with lowercase names. Each value
+    /// [`BlocklessHttp::get_headers`] finds is its own item. A header the
+    /// host fails to read is skipped rather than ending the iteration.
+    pub fn headers(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        KNOWN_HEADERS.iter().flat_map(move |&name| {
+            let values = self.find_headers(name).ok().flatten().unwrap_or_default();
+            values
+                .into_iter()
+                .filter(|value| !value.is_empty())
+                .map(move |value| (name.to_string(), value))
+        })
+    }
+
+    /// Hands the handle over to a [`Response`] that reads the body on demand.
+    pub fn send(self) -> Response {
+        Response {
+            code: self.code,
+            url: self.url.clone(),
+            source: ResponseSource::Host(self),
+            body: None,
+        }
+    }
+
+    /// Releases the handle now rather than when the value is dropped.
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// Issues a follow-up request and makes `self` refer to its response,
+    /// returning the new status. The `blockless_http` imports cannot reuse a
+    /// session, so this always opens a new handle and closes the old one;
+    /// whether the connection itself is reused is up to the host.
+    ///
+    /// The new handle is opened first, so if the request fails `self` still
+    /// holds the previous response. When that fails with
+    /// [`HttpErrorKind::TooManySessions`], the old handle is closed to make
+    /// room and the request is sent again; if it fails then too, `self` is
+    /// left closed and its reads fail with [`HttpErrorKind::InvalidHandle`].
+    pub fn reopen(&mut self, url: &str, opts: &FetchOptions) -> Result<CodeStatus, HttpErrorKind> {
+        *self = match Self::open(url, opts) {
+            Err(HttpErrorKind::TooManySessions) => {
+                self.release();
+                Self::open(url, opts)?
+            }
+            rs => rs?,
+        };
+        Ok(self.code)
+    }
+
+    fn release(&mut self) {
+        if !self.released {
+            unsafe {
+                http_close(self.inner);
+            }
+            OPEN_SESSIONS.fetch_sub(1, AtomicOrdering::Relaxed);
+            self.released = true;
+        }
+    }
+
+    /// Gives up on the response, e.g. midway through a streamed download.
+    /// The `blockless_http` imports have no cancellation call, so this
+    /// releases the handle exactly as [`BlocklessHttp::close`] does and it
+    /// is up to the host to stop the transfer. Body bytes not yet read are
+    /// discarded; whatever was already read stays with the caller.
+    pub fn abort(self) {
+        log_debug!("handle {}: aborted", self.inner);
+        drop(self);
+    }
+
+    /// Reads the whole body and releases the handle, whether or not the read
+    /// succeeds.
+    pub fn into_bytes(self) -> Result<Vec<u8>, HttpErrorKind> {
+        self.get_all_body()
+    }
+
+    /// [`BlocklessHttp::text`], releasing the handle afterwards.
+    pub fn into_string(self) -> Result<String, HttpErrorKind> {
+        self.text()
+    }
+
+    /// Reads the next piece of the body into `buf` and returns how many bytes
+    /// were written. `Ok(0)` always means end of body (or a HEAD response).
+    /// The host's "not ready yet" signal is retried internally, exactly as in
+    /// [`BlocklessHttp::get_all_body`], and only surfaces as
+    /// [`HttpErrorKind::WouldBlock`] once the bounded retries run out.
+    pub fn read_body(&self, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
+        self.read_chunk(buf).map(|n| n as u32)
+    }
+}
+
+/// Opens `url`, reads the whole body and releases the handle, returning the
+/// status code with the body. The handle is closed on every path, including
+/// a failed body read.
+pub fn fetch(url: &str, opts: &FetchOptions) -> Result<(CodeStatus, Vec<u8>), HttpErrorKind> {
+    let http = BlocklessHttp::open(url, opts)?;
+    http.check_json()?;
+    let body = http.get_all_body()?;
+    Ok((http.get_code(), body))
+}
+
+/// [`fetch`] for each request, returning the results in the same order.
+/// The host calls block, so the requests run one after another; each handle
+/// is released before the next request starts, and one failure does not
+/// stop the rest.
+pub fn fetch_all(
+    requests: &[(String, FetchOptions)],
+) -> Vec<Result<(CodeStatus, Vec<u8>), HttpErrorKind>> {
+    requests
+        .iter()
+        .map(|(url, opts)| fetch(url, opts))
+        .collect()
+}
+
+/// Issues a GET. The handle is released by [`BlocklessHttp::close`] or on drop.
+pub fn get(url: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+    BlocklessHttp::open(url, &FetchOptions::with_method(HttpMethod::Get))
+}
+
+/// Issues a POST carrying `body`. The handle is released by
+/// [`BlocklessHttp::close`] or on drop.
+pub fn post(url: &str, body: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+    BlocklessHttp::open(url, &FetchOptions::with_method(HttpMethod::Post).body(body))
+}
+
+/// Serializes `value` as the body of a POST with `Content-Type: application/json`.
+pub fn post_json<T: Serialize>(url: &str, value: &T) -> Result<BlocklessHttp, HttpErrorKind> {
+    let body = serde_json::to_string(value)?;
+    let opts = FetchOptions::with_method(HttpMethod::Post)
+        .header("Content-Type", "application/json")
+        .body(body);
+    BlocklessHttp::open(url, &opts)
+}
+
+/// Serializes `value` as an RFC 7386 merge patch and sends it as a PATCH
+/// with `Content-Type: application/merge-patch+json`.
+pub fn patch_json<T: Serialize>(url: &str, value: &T) -> Result<BlocklessHttp, HttpErrorKind> {
+    let body = serde_json::to_string(value)?;
+    let opts = FetchOptions::with_method(HttpMethod::Patch)
+        .header("Content-Type", "application/merge-patch+json")
+        .body(body);
+    BlocklessHttp::open(url, &opts)
+}
+
+/// Issues a HEAD and returns the status with the headers found by
+/// [`BlocklessHttp::get_all_headers`]. The body is never read and the handle
+/// is released before returning.
+pub fn head(url: &str) -> Result<(CodeStatus, HashMap<String, String>), HttpErrorKind> {
+    let http = BlocklessHttp::open(url, &FetchOptions::with_method(HttpMethod::Head))?;
+    Ok((http.get_code(), http.get_all_headers()?))
+}
+
+/// What a server reports in answer to an OPTIONS request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedOptions {
+    /// Methods from `Allow`.
+    pub allow: Vec<String>,
+    pub allow_origin: Option<String>,
+    pub allow_methods: Vec<String>,
+    pub allow_headers: Vec<String>,
+    pub allow_credentials: bool,
+    /// `Access-Control-Max-Age`, in seconds.
+    pub max_age: Option<u64>,
+}
+
+/// Issues an OPTIONS request and parses the `Allow` and
+/// `Access-Control-Allow-*` headers, plus `Access-Control-Max-Age`. The body
+/// is never read and the handle is released before returning.
+pub fn options(url: &str) -> Result<AllowedOptions, HttpErrorKind> {
+    let http = BlocklessHttp::open(url, &FetchOptions::with_method(HttpMethod::Options))?;
+    let header = |name: &str| http.find_header(name);
+    let list = |name: &str| -> Result<Vec<String>, HttpErrorKind> {
+        let value = header(name)?.unwrap_or_default();
+        Ok(value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect())
+    };
+    Ok(AllowedOptions {
+        allow: list("Allow")?,
+        allow_origin: header("Access-Control-Allow-Origin")?,
+        allow_methods: list("Access-Control-Allow-Methods")?,
+        allow_headers: list("Access-Control-Allow-Headers")?,
+        allow_credentials: header("Access-Control-Allow-Credentials")?
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true")),
+        max_age: header("Access-Control-Max-Age")?.and_then(|value| value.trim().parse().ok()),
+    })
+}
+
+/// Issues a DELETE. The handle is released by [`BlocklessHttp::close`] or on drop.
+pub fn delete(url: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+    BlocklessHttp::open(url, &FetchOptions::with_method(HttpMethod::Delete))
+}
+
+/// Issues a DELETE carrying a JSON `body`, e.g. for bulk deletes, with
+/// `Content-Type: application/json`.
+pub fn delete_with_body(url: &str, body: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+    let opts = FetchOptions::with_method(HttpMethod::Delete)
+        .header("Content-Type", "application/json")
+        .body(body);
+    BlocklessHttp::open(url, &opts)
+}
+
+/// Anything that can perform a request. Code that depends on this rather
+/// than on [`BlocklessHttp`] directly can be exercised without a live host.
+pub trait HttpClient {
+    fn open(&self, url: &str, opts: &FetchOptions) -> Result<Response, HttpErrorKind>;
+}
+
+/// [`HttpClient`] backed by the Blockless host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostClient;
+
+impl HttpClient for HostClient {
+    fn open(&self, url: &str, opts: &FetchOptions) -> Result<Response, HttpErrorKind> {
+        Ok(BlocklessHttp::open(url, opts)?.send())
+    }
+}
+
+/// A response whose body is read from the host on first use. Owning the
+/// handle, it can be passed around freely and closes the handle on drop.
+pub struct Response {
+    code: CodeStatus,
+    url: String,
+    source: ResponseSource,
+    body: Option<Vec<u8>>,
+}
+
+enum ResponseSource {
+    Host(BlocklessHttp),
+    Canned(HashMap<String, String>),
+}
+
+impl Response {
+    /// A response that is not backed by a host handle, e.g. for mocks.
+    pub fn new(code: CodeStatus, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            code,
+            url: String::new(),
+            source: ResponseSource::Canned(HashMap::new()),
+            body: Some(body.into()),
+        }
+    }
+
+    /// Adds a header to a response built with [`Response::new`]; has no
+    /// effect on responses read from the host.
+    /// Calling it again with the same name adds another value, as a server
+    /// repeating the header would.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        if let ResponseSource::Canned(headers) = &mut self.source {
+            headers
+                .entry(name.to_ascii_lowercase())
+                .and_modify(|values| {
+                    values.push('\n');
+                    values.push_str(value);
+                })
+                .or_insert_with(|| value.into());
+        }
+        self
+    }
+
+    /// Sets the URL [`Response::final_url`] reports for a response built
+    /// with [`Response::new`].
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn get_code(&self) -> CodeStatus {
+        self.code
+    }
+
+    /// The last URL fetched once redirects were followed, or the requested
+    /// URL when there were none. Use it to resolve relative links in the
+    /// body. Empty for a [`Response::new`] without [`Response::with_url`].
+    pub fn final_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from(self.code)
+    }
+
+    pub fn status_line(&self) -> StatusLine {
+        StatusLine::from_code(self.code)
+    }
+
+    pub fn is_success(&self) -> bool {
+        StatusCategory::from(self.code) == StatusCategory::Success
+    }
+
+    /// As [`BlocklessHttp::retry_after`].
+    pub fn retry_after(&self) -> Option<Duration> {
+        parse_retry_after(&self.find_header("Retry-After").ok()??)
+    }
+
+    /// As [`BlocklessHttp::error_for_status`].
+    pub fn error_for_status(self) -> Result<Self, HttpErrorKind> {
+        if self.is_success() {
+            Ok(self)
+        } else {
+            Err(HttpErrorKind::HttpStatusError(self.status().as_u16()))
+        }
+    }
+
+    /// True for `304 Not Modified`, the answer to a matching
+    /// [`FetchOptions::if_none_match`]; the body is empty and the cached copy
+    /// is still current.
+    pub fn not_modified(&self) -> bool {
+        self.code == 304
+    }
+
+    /// The first value of `header`, as with [`BlocklessHttp::get_header`].
+    pub fn get_header(&self, header: &str) -> Result<String, HttpErrorKind> {
+        Ok(self
+            .get_headers(header)?
+            .into_iter()
+            .next()
+            .unwrap_or_default())
+    }
+
+    pub fn get_headers(&self, header: &str) -> Result<Vec<String>, HttpErrorKind> {
+        match &self.source {
+            ResponseSource::Host(http) => http.get_headers(header),
+            ResponseSource::Canned(headers) => headers
+                .get(&header.to_ascii_lowercase())
+                .map(|raw| header_values(raw).map(String::from).collect())
+                .ok_or(HttpErrorKind::HeaderNotFound),
+        }
+    }
+
+    fn find_header(&self, header: &str) -> Result<Option<String>, HttpErrorKind> {
+        match &self.source {
+            ResponseSource::Host(http) => http.find_header(header),
+            ResponseSource::Canned(_) => match self.get_header(header) {
+                Ok(value) => Ok(Some(value)),
+                Err(HttpErrorKind::HeaderNotFound) => Ok(None),
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Reads the body on the first call and returns the buffered bytes after.
+    pub fn bytes(&mut self) -> Result<&[u8], HttpErrorKind> {
+        if self.body.is_none() {
+            self.body = Some(self.read_body()?);
+        }
+        Ok(self.body.as_deref().unwrap_or_default())
+    }
+
+    pub fn into_body(mut self) -> Result<Vec<u8>, HttpErrorKind> {
+        match self.body.take() {
+            Some(body) => Ok(body),
+            None => self.read_body(),
+        }
+    }
+
+    /// Decodes the body by its declared charset, as [`BlocklessHttp::text`].
+    pub fn text(self) -> Result<String, HttpErrorKind> {
+        let content_type = self.find_header("Content-Type").ok().flatten();
+        decode_text(self.into_body()?, content_type.as_deref())
+    }
+
+    /// As [`BlocklessHttp::get_json`].
+    pub fn json<T: DeserializeOwned>(self) -> Result<T, FetchError> {
+        Ok(serde_json::from_slice(strip_bom(&self.into_body()?))?)
+    }
+
+    /// As [`BlocklessHttp::get_json_or_error`].
+    pub fn json_or_error<T, E>(self) -> Result<Result<T, E>, FetchError>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned,
+    {
+        json_or_error(&self.into_body()?)
+    }
+
+    // Every header that can be found, lowercase; see `get_all_headers`.
+    #[cfg(feature = "record")]
+    pub(crate) fn all_headers(&self) -> Result<HashMap<String, String>, HttpErrorKind> {
+        match &self.source {
+            ResponseSource::Host(http) => http.get_all_headers(),
+            ResponseSource::Canned(headers) => Ok(headers.clone()),
+        }
+    }
+
+    fn read_body(&self) -> Result<Vec<u8>, HttpErrorKind> {
+        match &self.source {
+            ResponseSource::Host(http) => http.get_all_body(),
+            ResponseSource::Canned(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+fn json_or_error<T, E>(body: &[u8]) -> Result<Result<T, E>, FetchError>
+where
+    T: DeserializeOwned,
+    E: DeserializeOwned,
+{
+    let body = strip_bom(body);
+    match serde_json::from_slice(body) {
+        Ok(value) => Ok(Ok(value)),
+        Err(e) => match serde_json::from_slice(body) {
+            Ok(error) => Ok(Err(error)),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+fn encode_pairs<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    pairs
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+pub(crate) fn append_query(url: &str, query: &[(String, String)]) -> String {
+    if query.is_empty() {
+        return url.to_string();
+    }
+    let (base, fragment) = match url.find('#') {
+        Some(i) => url.split_at(i),
+        None => (url, ""),
+    };
+    let encoded = encode_pairs(query.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    let separator = match base.find('?') {
+        None => "?",
+        Some(_) if base.ends_with('?') || base.ends_with('&') => "",
+        Some(_) => "&",
+    };
+    format!("{}{}{}{}", base, separator, encoded, fragment)
+}
+
+// A header value as returned by the host, one occurrence per line where the
+// backend keeps repeats apart; see `BlocklessHttp::get_headers`.
+fn header_values(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split('\n').map(|value| value.trim_end_matches('\r'))
+}
+
+fn is_redirect(code: CodeStatus) -> bool {
+    matches!(code, 301 | 302 | 303 | 307 | 308)
+}
+
+// Resolves a `Location` value against the URL that returned it
+// (RFC 3986 §5.2.2).
+fn resolve_location(base: &str, location: &str) -> String {
+    let base = UrlParts::parse(base);
+    let reference = UrlParts::parse(location);
+    let mut target = UrlParts {
+        fragment: reference.fragment,
+        ..base
+    };
+    let path;
+    if reference.scheme.is_some() || reference.authority.is_some() {
+        target.scheme = reference.scheme.or(base.scheme);
+        target.authority = reference.authority;
+        target.query = reference.query;
+        path = remove_dot_segments(reference.path);
+    } else if reference.path.is_empty() {
+        target.query = reference.query.or(base.query);
+        path = base.path.to_string();
+    } else {
+        target.query = reference.query;
+        path = if reference.path.starts_with('/') {
+            remove_dot_segments(reference.path)
+        } else if base.authority.is_some() && base.path.is_empty() {
+            remove_dot_segments(&format!("/{}", reference.path))
+        } else {
+            let dir = base.path.rfind('/').map_or("", |i| &base.path[..=i]);
+            remove_dot_segments(&format!("{}{}", dir, reference.path))
+        };
+    }
+    target.path = &path;
+    target.to_string()
+}
+
+// The five components of a URL or relative reference (RFC 3986 §3).
+#[derive(Debug, Clone, Copy)]
+struct UrlParts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> UrlParts<'a> {
+    fn parse(url: &'a str) -> Self {
+        let (rest, fragment) = match url.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (url, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (scheme, rest) = match rest.split_once(':') {
+            Some((scheme, rest)) if is_scheme(scheme) => (Some(scheme), rest),
+            _ => (None, rest),
+        };
+        let (authority, path) = match rest.strip_prefix("//") {
+            Some(rest) => {
+                let end = rest.find('/').unwrap_or(rest.len());
+                (Some(&rest[..end]), &rest[end..])
+            }
+            None => (None, rest),
+        };
+        UrlParts {
+            scheme,
+            authority,
+            path,
+            query,
+            fragment,
+        }
+    }
+}
+
+impl std::fmt::Display for UrlParts<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+        if let Some(authority) = self.authority {
+            write!(f, "//{}", authority)?;
+        }
+        f.write_str(self.path)?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_scheme(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+// Resolves `.` and `..` segments (RFC 3986 §5.2.4).
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::new();
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") || input == "/." {
+            input = if input == "/." { "/" } else { &input[2..] };
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let start = usize::from(input.starts_with('/'));
+            let end = input[start..].find('/').map_or(input.len(), |i| start + i);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
 }
 
-impl FetchOptions {
-    pub fn new(method: &str) -> Self {
-        FetchOptions {
-            method: method.into()
+// Scheme, host and port, lowercase and with the scheme's default port filled
+// in, so `http://Example.com` and `http://example.com:80` are one origin.
+fn origin(url: &str) -> Option<(String, String, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    let host = authority.rsplit('@').next()?.to_ascii_lowercase();
+    // A colon inside brackets belongs to an IPv6 address, not a port.
+    let (host, port) = match host.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host.to_string(), port.parse().ok()?),
+        _ => {
+            let port = match scheme.as_str() {
+                "https" => 443,
+                "http" => 80,
+                _ => 0,
+            };
+            (host, port)
         }
+    };
+    Some((scheme, host, port))
+}
+
+// Headers that must not follow a redirect to another origin, nor be
+// written to a recording.
+pub(crate) fn is_credential_header(name: &str) -> bool {
+    ["authorization", "cookie", "proxy-authorization"]
+        .iter()
+        .any(|credential| name.eq_ignore_ascii_case(credential))
+}
+
+/// Checks that `url` has a scheme and a non-empty host before it reaches the
+/// host, and percent-encodes characters that are never valid in a URL
+/// (whitespace, controls, non-ASCII and the RFC 3986 "unwise" set).
+fn normalize_url(url: &str) -> Result<String, HttpErrorKind> {
+    let invalid = |reason: &str| {
+        log_warn!("invalid url {:?}: {}", url, reason);
+        Err(HttpErrorKind::InvalidUrl)
+    };
+    let url = url.trim();
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return invalid("missing scheme");
+    };
+    let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !valid_scheme {
+        return invalid("malformed scheme");
     }
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if host.is_empty() || host.starts_with(':') {
+        return invalid("missing host");
+    }
+    Ok(encode_unsafe(url))
+}
 
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(&self).unwrap()
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
     }
+    let at = parse_http_date(value)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(Duration::from_secs(at.saturating_sub(now)))
 }
 
-pub type Handle = u32;
+// Seconds since the Unix epoch for an IMF-fixdate such as
+// `Sun, 06 Nov 1994 08:49:37 GMT`, the form servers are required to send.
+fn parse_http_date(value: &str) -> Option<u64> {
+    let (_, rest) = value.split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|&m| m == month)? as u64 + 1;
+    let day: u64 = day.parse().ok()?;
+    let year: u64 = year.parse().ok()?;
+    let mut hms = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (h, m, s) = (hms.next()??, hms.next()??, hms.next()??);
+    if year < 1970 || !(1..=31).contains(&day) || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
 
-pub type CodeStatus = u32;
+    // Days from 1970-01-01 to the date (proleptic Gregorian calendar).
+    let (y, mp) = if month > 2 {
+        (year, month - 3)
+    } else {
+        (year - 1, month + 9)
+    };
+    let era = y / 400;
+    let yoe = y % 400;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468)?;
+    Some(days * 86_400 + h * 3600 + m * 60 + s)
+}
 
-pub struct BlocklessHttp {
-    inner: Handle,
-    code: CodeStatus,
+// Whole seconds for the host, rounding any fraction up so a short timeout
+// does not turn into "no timeout".
+fn timeout_secs(timeout: Duration) -> u32 {
+    let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+    u32::try_from(secs).unwrap_or(u32::MAX)
 }
 
-pub struct HttpOptions {
-    method: String,
-    connect_timeout: u32,
-    read_timeout: u32,
-    body: Option<String>,
+// Whether `elapsed` has reached a timeout of `secs` seconds; `0` never expires.
+fn exceeded(elapsed: Duration, secs: u32) -> bool {
+    secs > 0 && elapsed >= Duration::from_secs(secs.into())
 }
 
-impl HttpOptions {
-    pub fn new(method: &str, connect_timeout: u32, read_timeout: u32) -> Self {
-        HttpOptions {
-            method: method.into(),
-            connect_timeout,
-            read_timeout,
-            body: None,
-        }
-    }
+// Incremental HTTP/1.1 chunked decoder: `feed` takes framed bytes as they
+// arrive, `take` hands out the payload.
+#[derive(Debug, Default)]
+struct ChunkDecoder {
+    state: ChunkState,
+    payload: Vec<u8>,
+}
 
-    pub fn to_json(&self) -> Value {
-        json!({
-            "method": self.method,
-            "connectTimeout": self.connect_timeout,
-            "readTimeout": self.read_timeout,
-            "headers": "{}",
-            "body": self.body,
-        })
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ChunkState {
+    /// At the start of a size line.
+    #[default]
+    Size,
+    /// Reading the hex size.
+    SizeDigits {
+        size: usize,
+    },
+    /// Skipping chunk extensions up to the line's CR.
+    Extension {
+        size: usize,
+    },
+    SizeLf {
+        size: usize,
+    },
+    Data {
+        remaining: usize,
+    },
+    DataCr,
+    DataLf,
+    /// After the last chunk; `empty` while the current trailer line is.
+    Trailer {
+        empty: bool,
+    },
+    TrailerLf {
+        empty: bool,
+    },
+    Done,
 }
 
-impl BlocklessHttp {
-    pub fn open(url: &str, opts: &FetchOptions) -> Result<Self, HttpErrorKind> {
-        let http_opts = HttpOptions::new(&opts.method, 30, 10);
-        let http_opts_str = serde_json::to_string(&http_opts.to_json()).unwrap();
+impl ChunkDecoder {
+    fn feed(&mut self, mut input: &[u8]) -> Result<(), HttpErrorKind> {
+        use ChunkState::*;
 
-        let mut fd = 0;
-        let mut status = 0;
-        let rs = unsafe {
-            http_open(
-                url.as_ptr(),
-                url.len() as _,
-                http_opts_str.as_ptr(),
-                http_opts_str.len() as _,
-                &mut fd,
-                &mut status,
-            )
-        };
-        if rs != 0 {
-            return Err(HttpErrorKind::from(rs));
+        let invalid = Err(HttpErrorKind::InvalidEncoding);
+        while let Some(&b) = input.first() {
+            if let Data { remaining } = self.state {
+                let n = remaining.min(input.len());
+                self.payload.extend_from_slice(&input[..n]);
+                input = &input[n..];
+                self.state = match remaining - n {
+                    0 => DataCr,
+                    remaining => Data { remaining },
+                };
+                continue;
+            }
+            self.state = match (self.state, b) {
+                (Size, _) if b.is_ascii_hexdigit() => SizeDigits { size: hex_value(b) },
+                (SizeDigits { size }, _) if b.is_ascii_hexdigit() => match size.checked_mul(16) {
+                    Some(size) => SizeDigits {
+                        size: size + hex_value(b),
+                    },
+                    None => return invalid,
+                },
+                (SizeDigits { size }, b';' | b' ' | b'\t') => Extension { size },
+                (SizeDigits { size } | Extension { size }, b'\r') => SizeLf { size },
+                (Extension { size }, _) => Extension { size },
+                (SizeLf { size: 0 }, b'\n') => Trailer { empty: true },
+                (SizeLf { size }, b'\n') => Data { remaining: size },
+                (DataCr, b'\r') => DataLf,
+                (DataLf, b'\n') => Size,
+                (Trailer { empty }, b'\r') => TrailerLf { empty },
+                (Trailer { .. }, _) => Trailer { empty: false },
+                (TrailerLf { empty: true }, b'\n') => Done,
+                (TrailerLf { empty: false }, b'\n') => Trailer { empty: true },
+                // Anything after the final CRLF is not part of the body.
+                (Done, _) => return Ok(()),
+                _ => return invalid,
+            };
+            input = &input[1..];
         }
-        Ok(Self {
-            inner: fd,
-            code: status,
-        })
+        Ok(())
     }
 
-    pub fn get_code(&self) -> CodeStatus {
-        self.code
+    // Moves up to `buf.len()` payload bytes into `buf`.
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.payload.len());
+        buf[..n].copy_from_slice(&self.payload[..n]);
+        self.payload.drain(..n);
+        n
     }
 
-    pub fn get_all_body(&self) -> Result<Vec<u8>, HttpErrorKind> {
-        let mut vec = Vec::new();
-        loop {
-            let mut buf = [0u8; 1024];
-            let mut num: u32 = 0;
-            let rs =
-                unsafe { http_read_body(self.inner, buf.as_mut_ptr(), buf.len() as _, &mut num) };
+    fn is_done(&self) -> bool {
+        self.state == ChunkState::Done && self.payload.is_empty()
+    }
 
-            if rs == u32::MAX {
-                continue;
-            } else if rs != 0 {
-                return Err(HttpErrorKind::from(rs));
-            } else {
-                match num.cmp(&0) {
-                    Ordering::Greater => vec.extend_from_slice(&buf[0..num as _]),
-                    _ => break,
-                }
-            }
+    // At the end of the raw body: the last chunk must have been seen. A
+    // missing final CRLF after it is tolerated.
+    fn finish(&self) -> Result<(), HttpErrorKind> {
+        match self.state {
+            ChunkState::Done | ChunkState::Trailer { empty: true } => Ok(()),
+            _ => Err(HttpErrorKind::InvalidEncoding),
         }
-        Ok(vec)
     }
+}
 
-    pub fn get_header(&self, header: &str) -> Result<String, HttpErrorKind> {
-        let mut vec = Vec::new();
-        loop {
-            let mut buf = [0u8; 1024];
-            let mut num: u32 = 0;
-            let rs = unsafe {
-                http_read_header(
-                    self.inner,
-                    header.as_ptr(),
-                    header.len() as _,
-                    buf.as_mut_ptr(),
-                    buf.len() as _,
-                    &mut num,
-                )
-            };
+fn hex_value(b: u8) -> usize {
+    (b as char).to_digit(16).unwrap_or(0) as usize
+}
 
-            if rs == u32::MAX {
-                continue;
-            } else if rs != 0 {
-                return Err(HttpErrorKind::from(rs));
-            } else {
-                match num.cmp(&0) {
-                    Ordering::Greater => vec.extend_from_slice(&buf[0..num as _]),
-                    _ => break,
-                }
+// Which timeout a failed `http_open` after `elapsed` most likely hit. The
+// host does not say, so of the limits that have run out, the longest is
+// taken: a connect timeout fails at about `connect` seconds, while a read
+// timeout fails `read` seconds after connecting and so at least that late.
+// Equal limits are reported as a read timeout.
+fn open_timeout(elapsed: Duration, connect: u32, read: u32) -> Option<HttpErrorKind> {
+    match (exceeded(elapsed, connect), exceeded(elapsed, read)) {
+        (true, true) if connect > read => Some(HttpErrorKind::ConnectTimeout),
+        (_, true) => Some(HttpErrorKind::ReadTimeout),
+        (true, false) => Some(HttpErrorKind::ConnectTimeout),
+        (false, false) => None,
+    }
+}
+
+fn retry_pending(mut call: impl FnMut() -> u32) -> Result<(), HttpErrorKind> {
+    for _ in 0..MAX_READ_ATTEMPTS {
+        match call() {
+            u32::MAX => {
+                LAST_HOST_CODE.with(|code| code.set(u32::MAX));
+                std::thread::sleep(READ_RETRY_DELAY);
             }
+            rs => return HttpErrorKind::check(rs),
         }
-        String::from_utf8(vec).map_err(|_| HttpErrorKind::Utf8Error)
     }
+    Err(HttpErrorKind::WouldBlock)
+}
 
-    pub fn close(self) {
-        unsafe {
-            http_close(self.inner);
-        }
+impl std::fmt::Debug for BlocklessHttp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlocklessHttp")
+            .field("inner", &self.inner)
+            .field("code", &self.code)
+            .finish()
     }
+}
 
-    pub fn read_body(&self, buf: &mut [u8]) -> Result<u32, HttpErrorKind> {
-        let mut num: u32 = 0;
-        let rs = unsafe { http_read_body(self.inner, buf.as_mut_ptr(), buf.len() as _, &mut num) };
-        if rs != 0 {
-            return Err(HttpErrorKind::from(rs));
-        }
-        Ok(num)
+impl Drop for BlocklessHttp {
+    fn drop(&mut self) {
+        self.release();
     }
 }
 
-#[derive(Debug)]
+impl std::io::Read for BlocklessHttp {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.read_chunk(buf)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HttpErrorKind {
     InvalidDriver,
     InvalidHandle,
@@ -168,8 +2384,66 @@ pub enum HttpErrorKind {
     InvalidUrl,
     RequestError,
     RuntimeError,
+    /// The host reported a failure once the connect timeout had elapsed,
+    /// and before a longer read timeout had.
+    ConnectTimeout,
+    /// The host reported a failure once the read timeout had elapsed while
+    /// waiting for the response or a piece of the body. When both timeouts
+    /// have run out, the longer one decides between this and
+    /// `ConnectTimeout`, since the host does not report the phase.
+    ReadTimeout,
+    /// The [`FetchOptions::total_timeout`] deadline passed.
+    Timeout,
     TooManySessions,
     PermissionDeny,
+    /// Serializing or parsing JSON failed; carries serde's description,
+    /// including the line and column where parsing stopped.
+    JsonError(String),
+    WouldBlock,
+    TooManyRedirects,
+    BodyTooLarge,
+    /// The body ended before its advertised `Content-Length`, see
+    /// [`FetchOptions::detect_truncation`].
+    TruncatedBody,
+    /// The response was not JSON although [`FetchOptions::expect_json`] was
+    /// set; carries the `Content-Type` received, empty if there was none.
+    UnexpectedContentType(String),
+    /// A non-2xx status, from `error_for_status`.
+    HttpStatusError(u16),
+    Io(std::io::ErrorKind),
+    Unknown(u32),
+}
+
+impl HttpErrorKind {
+    /// Turns a host return code into a `Result`; `0` is the host's success
+    /// code and is never converted into an error. The code is kept for
+    /// [`last_host_code`].
+    pub(crate) fn check(rs: u32) -> Result<(), HttpErrorKind> {
+        LAST_HOST_CODE.with(|code| code.set(rs));
+        match rs {
+            0 => Ok(()),
+            rs => Err(HttpErrorKind::from(rs)),
+        }
+    }
+
+    /// Transient failures worth retrying: `RequestError`, `RuntimeError` and
+    /// the timeouts. Everything else (bad URLs, denied permissions, ...) fails
+    /// the same way on every attempt.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RequestError | Self::RuntimeError | Self::ConnectTimeout | Self::ReadTimeout
+        )
+    }
+
+    // The host has no timeout code, so a generic failure that arrives once a
+    // timeout has run out is taken to be that timeout.
+    fn timed_out(self, timeout: HttpErrorKind) -> HttpErrorKind {
+        match self {
+            Self::RequestError | Self::RuntimeError => timeout,
+            e => e,
+        }
+    }
 }
 
 impl std::error::Error for HttpErrorKind {}
@@ -178,7 +2452,7 @@ impl std::fmt::Display for HttpErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             Self::InvalidDriver => write!(f, "Invalid Driver"),
-            Self::InvalidHandle => write!(f, "Invalid Error"),
+            Self::InvalidHandle => write!(f, "Invalid handle"),
             Self::MemoryAccessError => write!(f, "Memory Access Error"),
             Self::BufferTooSmall => write!(f, "Buffer too small"),
             Self::HeaderNotFound => write!(f, "Header not found"),
@@ -187,14 +2461,102 @@ impl std::fmt::Display for HttpErrorKind {
             Self::InvalidMethod => write!(f, "Invalid method"),
             Self::InvalidEncoding => write!(f, "Invalid encoding"),
             Self::InvalidUrl => write!(f, "Invalid url"),
-            Self::RequestError => write!(f, "Request url"),
+            Self::RequestError => write!(f, "Request failed"),
             Self::RuntimeError => write!(f, "Runtime error"),
+            Self::ConnectTimeout => write!(f, "Connect timed out"),
+            Self::ReadTimeout => write!(f, "Read timed out"),
+            Self::Timeout => write!(f, "Deadline exceeded"),
             Self::TooManySessions => write!(f, "Too many sessions"),
             Self::PermissionDeny => write!(f, "Permission deny."),
+            Self::JsonError(ref message) => write!(f, "Json error: {}", message),
+            Self::WouldBlock => write!(f, "Host not ready, retries exhausted"),
+            Self::TooManyRedirects => write!(f, "Too many redirects"),
+            Self::BodyTooLarge => write!(f, "Body too large"),
+            Self::TruncatedBody => write!(f, "Body shorter than Content-Length"),
+            Self::UnexpectedContentType(ref content_type) => {
+                write!(f, "Unexpected content type: {:?}", content_type)
+            }
+            Self::HttpStatusError(code) => write!(f, "Http status {}", StatusCode::from(code)),
+            Self::Io(kind) => write!(f, "Io error: {}", kind),
+            Self::Unknown(code) => write!(f, "Unknown error code: {}", code),
+        }
+    }
+}
+
+impl From<HttpErrorKind> for std::io::Error {
+    fn from(e: HttpErrorKind) -> std::io::Error {
+        std::io::Error::other(e)
+    }
+}
+
+impl From<serde_json::Error> for HttpErrorKind {
+    fn from(e: serde_json::Error) -> HttpErrorKind {
+        HttpErrorKind::JsonError(e.to_string())
+    }
+}
+
+/// The error of the higher-level helpers such as [`BlocklessHttp::get_json`]
+/// and [`crate::coingecko::fetch_coin_price`], split by where things went
+/// wrong so callers can tell retryable failures from permanent ones.
+#[derive(Debug)]
+pub enum FetchError {
+    /// The request or reading the response failed.
+    Transport(HttpErrorKind),
+    /// The server answered with a non-2xx status.
+    Status(u16),
+    /// The body was not the expected JSON.
+    Parse(serde_json::Error),
+    /// The API reported an error in its own error format.
+    Api(u32, String),
+}
+
+impl FetchError {
+    /// Transport errors for which [`HttpErrorKind::is_retryable`] holds, and
+    /// `429` or `5xx` statuses.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Self::Transport(ref e) => e.is_retryable(),
+            Self::Status(code) => code == 429 || StatusCode::from(code).is_server_error(),
+            Self::Parse(_) | Self::Api(..) => false,
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Transport(ref e) => Some(e),
+            Self::Parse(ref e) => Some(e),
+            Self::Status(_) | Self::Api(..) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Transport(ref e) => write!(f, "{}", e),
+            Self::Status(code) => write!(f, "Http status {}", StatusCode::from(code)),
+            Self::Parse(ref e) => write!(f, "Parse error: {}", e),
+            Self::Api(code, ref message) => write!(f, "Api error {}: {}", code, message),
         }
     }
 }
 
+impl From<HttpErrorKind> for FetchError {
+    fn from(e: HttpErrorKind) -> FetchError {
+        FetchError::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> FetchError {
+        FetchError::Parse(e)
+    }
+}
+
+/// Maps a non-zero host return code to its error. `0` means success and is
+/// never converted by this crate; passing it anyway yields `Unknown(0)`.
 impl From<u32> for HttpErrorKind {
     fn from(i: u32) -> HttpErrorKind {
         match i {
@@ -211,12 +2573,21 @@ impl From<u32> for HttpErrorKind {
             11 => HttpErrorKind::RuntimeError,
             12 => HttpErrorKind::TooManySessions,
             13 => HttpErrorKind::PermissionDeny,
-            _ => HttpErrorKind::RuntimeError,
+            _ => HttpErrorKind::Unknown(i),
         }
     }
 }
 
-#[link(wasm_import_module = "blockless_http")]
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+use crate::native::{http_close, http_open, http_read_body, http_read_header};
+
+// Older host builds export the same functions from the default `env` module.
+#[cfg(any(target_arch = "wasm32", not(feature = "native")))]
+#[cfg_attr(
+    not(feature = "import-module-legacy"),
+    link(wasm_import_module = "blockless_http")
+)]
+#[cfg_attr(feature = "import-module-legacy", link(wasm_import_module = "env"))]
 extern "C" {
     #[link_name = "http_req"]
     pub(crate) fn http_open(
@@ -243,4 +2614,650 @@ extern "C" {
 
     #[link_name = "http_close"]
     pub(crate) fn http_close(handle: u32) -> u32;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::Write as _;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+
+    // Answers one connection per entry of `responses`, in order, on a local
+    // port, and passes each request it read back through the receiver.
+    pub(crate) fn serve(responses: Vec<Vec<u8>>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut stream);
+                let _ = stream.write_all(&response);
+                let _ = tx.send(request);
+            }
+        });
+        (url, rx)
+    }
+
+    // A response with `head` ("200 OK\r\nName: value...") and a
+    // Content-Length for `body`.
+    pub(crate) fn response(head: &str, body: &[u8]) -> Vec<u8> {
+        let mut raw = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            head,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+        raw
+    }
+
+    // The request head and, going by its Content-Length, its body.
+    fn read_request(stream: &mut TcpStream) -> String {
+        use std::io::Read as _;
+
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let head_end = loop {
+            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+            match stream.read(&mut buf).unwrap() {
+                0 => return String::from_utf8_lossy(&request).into_owned(),
+                n => request.extend_from_slice(&buf[..n]),
+            }
+        };
+        let head = String::from_utf8_lossy(&request[..head_end]).to_ascii_lowercase();
+        let body_len: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |len| len.trim().parse().unwrap());
+        while request.len() < head_end + body_len {
+            match stream.read(&mut buf).unwrap() {
+                0 => break,
+                n => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        String::from_utf8_lossy(&request).into_owned()
+    }
+
+    #[test]
+    fn http_options_carry_the_body() {
+        let opts = FetchOptions::with_method(HttpMethod::Post).body(r#"{"id":1}"#);
+        let json = opts.http_options().to_json();
+        assert_eq!(json["method"], "POST");
+        assert_eq!(json["body"], r#"{"id":1}"#);
+
+        let json = FetchOptions::default().http_options().to_json();
+        assert!(json["body"].is_null());
+    }
+
+    #[test]
+    fn get_header_ignores_case() {
+        let (url, _) = serve(vec![response("200 OK\r\nContent-Type: text/plain", b"ok")]);
+        let http = get(&url).unwrap();
+        for name in ["Content-Type", "content-type", "CONTENT-type"] {
+            assert_eq!(http.get_header(name).unwrap(), "text/plain");
+        }
+
+        let canned = Response::new(200, "ok").with_header("Content-Type", "text/plain");
+        assert_eq!(canned.get_header("CONTENT-TYPE").unwrap(), "text/plain");
+    }
+
+    #[test]
+    fn status_category_boundaries() {
+        assert_eq!(StatusCategory::from(199), StatusCategory::Informational);
+        assert_eq!(StatusCategory::from(200), StatusCategory::Success);
+        assert_eq!(StatusCategory::from(299), StatusCategory::Success);
+        assert_eq!(StatusCategory::from(300), StatusCategory::Redirection);
+    }
+
+    #[test]
+    fn error_display() {
+        let cases = [
+            (HttpErrorKind::InvalidDriver, "Invalid Driver"),
+            (HttpErrorKind::InvalidHandle, "Invalid handle"),
+            (HttpErrorKind::MemoryAccessError, "Memory Access Error"),
+            (HttpErrorKind::BufferTooSmall, "Buffer too small"),
+            (HttpErrorKind::HeaderNotFound, "Header not found"),
+            (HttpErrorKind::Utf8Error, "Utf8 error"),
+            (
+                HttpErrorKind::DestinationNotAllowed,
+                "Destination not allowed",
+            ),
+            (HttpErrorKind::InvalidMethod, "Invalid method"),
+            (HttpErrorKind::InvalidEncoding, "Invalid encoding"),
+            (HttpErrorKind::InvalidUrl, "Invalid url"),
+            (HttpErrorKind::RequestError, "Request failed"),
+            (HttpErrorKind::RuntimeError, "Runtime error"),
+            (HttpErrorKind::ConnectTimeout, "Connect timed out"),
+            (HttpErrorKind::ReadTimeout, "Read timed out"),
+            (HttpErrorKind::Timeout, "Deadline exceeded"),
+            (HttpErrorKind::TooManySessions, "Too many sessions"),
+            (HttpErrorKind::PermissionDeny, "Permission deny."),
+            (HttpErrorKind::JsonError("eof".into()), "Json error: eof"),
+            (
+                HttpErrorKind::WouldBlock,
+                "Host not ready, retries exhausted",
+            ),
+            (HttpErrorKind::TooManyRedirects, "Too many redirects"),
+            (HttpErrorKind::BodyTooLarge, "Body too large"),
+            (
+                HttpErrorKind::TruncatedBody,
+                "Body shorter than Content-Length",
+            ),
+            (
+                HttpErrorKind::UnexpectedContentType("text/html".into()),
+                "Unexpected content type: \"text/html\"",
+            ),
+            (
+                HttpErrorKind::HttpStatusError(404),
+                "Http status 404 Not Found",
+            ),
+            (
+                HttpErrorKind::Io(std::io::ErrorKind::UnexpectedEof),
+                "Io error: unexpected end of file",
+            ),
+            (HttpErrorKind::Unknown(99), "Unknown error code: 99"),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn host_code_zero_is_success() {
+        assert_eq!(HttpErrorKind::check(0), Ok(()));
+        assert_eq!(HttpErrorKind::check(10), Err(HttpErrorKind::RequestError));
+        assert_eq!(HttpErrorKind::from(0), HttpErrorKind::Unknown(0));
+    }
+
+    #[test]
+    fn redirects_drop_credentials_across_origins() {
+        let (other, other_requests) = serve(vec![response("200 OK", b"")]);
+        let location = format!("302 Found\r\nLocation: {}/landing", other);
+        let (url, requests) = serve(vec![
+            response("302 Found\r\nLocation: /next", b""),
+            response(&location, b""),
+        ]);
+        let opts = FetchOptions::default()
+            .follow_redirects(5)
+            .bearer_auth("SECRET")
+            .header("Cookie", "session=1");
+        let http = BlocklessHttp::open(&url, &opts).unwrap();
+        assert_eq!(http.get_code(), 200);
+
+        // Same origin: still authorized.
+        let _ = requests.recv().unwrap();
+        let same = requests.recv().unwrap().to_ascii_lowercase();
+        assert!(same.starts_with("get /next "));
+        assert!(same.contains("authorization: bearer secret"));
+        assert!(same.contains("cookie: session=1"));
+
+        let cross = other_requests.recv().unwrap().to_ascii_lowercase();
+        assert!(cross.starts_with("get /landing "));
+        assert!(!cross.contains("authorization"));
+        assert!(!cross.contains("cookie"));
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn decoded_body_respects_max_body_size() {
+        let zeros = gzip(&vec![0u8; 1024 * 1024]);
+        let (url, _) = serve(vec![
+            response("200 OK\r\nContent-Encoding: gzip", &zeros),
+            response("200 OK\r\nContent-Encoding: gzip", &zeros),
+        ]);
+        let opts = FetchOptions::default().max_body_size(64 * 1024);
+        assert!(zeros.len() < 64 * 1024);
+        let http = BlocklessHttp::open(&url, &opts).unwrap();
+        assert_eq!(
+            http.get_all_body_decoded(),
+            Err(HttpErrorKind::BodyTooLarge)
+        );
+
+        let http = get(&url).unwrap();
+        assert_eq!(http.get_all_body_decoded().unwrap().len(), 1024 * 1024);
+    }
+
+    #[test]
+    fn reads_a_multi_megabyte_body() {
+        let body: Vec<u8> = (0..5 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let (url, _) = serve(vec![response("200 OK", &body), response("200 OK", &body)]);
+        let http = get(&url).unwrap();
+        assert_eq!(http.get_all_body().unwrap(), body);
+
+        let opts = FetchOptions::default().read_buffer_size(64 * 1024);
+        let http = BlocklessHttp::open(&url, &opts).unwrap();
+        let mut buf = Vec::new();
+        assert_eq!(http.read_all_into(&mut buf).unwrap(), body.len());
+        assert_eq!(buf, body);
+    }
+
+    #[test]
+    fn head_response_has_no_body() {
+        let head = b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\nConnection: close\r\n\r\n";
+        let (url, requests) = serve(vec![head.to_vec()]);
+        let http = BlocklessHttp::open(&url, &FetchOptions::with_method(HttpMethod::Head)).unwrap();
+        assert!(requests.recv().unwrap().starts_with("HEAD / "));
+        assert_eq!(http.content_length(), Some(1000));
+        assert_eq!(http.get_all_body().unwrap(), Vec::<u8>::new());
+        assert_eq!(http.read_body(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn all_headers_keep_repeated_values() {
+        let (url, _) = serve(vec![response(
+            "200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Type: text/plain",
+            b"",
+        )]);
+        let headers = get(&url).unwrap().get_all_headers().unwrap();
+        assert_eq!(headers["set-cookie"], "a=1\nb=2");
+        assert_eq!(headers["content-type"], "text/plain");
+    }
+
+    #[test]
+    fn repeated_headers_return_every_value() {
+        let canned = Response::new(200, "")
+            .with_header("Set-Cookie", "a=1; Path=/")
+            .with_header("set-cookie", "b=2");
+        assert_eq!(
+            canned.get_headers("Set-Cookie").unwrap(),
+            ["a=1; Path=/", "b=2"]
+        );
+        assert_eq!(canned.get_header("Set-Cookie").unwrap(), "a=1; Path=/");
+
+        let (url, _) = serve(vec![response(
+            "200 OK\r\nSet-Cookie: a=1; Path=/\r\nSet-Cookie: b=2",
+            b"",
+        )]);
+        let http = get(&url).unwrap();
+        assert_eq!(
+            http.get_headers("set-cookie").unwrap(),
+            ["a=1; Path=/", "b=2"]
+        );
+        assert_eq!(http.get_header("Set-Cookie").unwrap(), "a=1; Path=/");
+    }
+
+    #[test]
+    fn open_timeouts_are_told_apart_by_elapsed_time() {
+        let secs = Duration::from_secs;
+        assert_eq!(open_timeout(secs(0), 1, 2), None);
+        assert_eq!(
+            open_timeout(secs(1), 1, 2),
+            Some(HttpErrorKind::ConnectTimeout)
+        );
+        assert_eq!(
+            open_timeout(secs(2), 1, 2),
+            Some(HttpErrorKind::ReadTimeout)
+        );
+        assert_eq!(
+            open_timeout(secs(2), 5, 2),
+            Some(HttpErrorKind::ReadTimeout)
+        );
+        assert_eq!(
+            open_timeout(secs(5), 5, 2),
+            Some(HttpErrorKind::ConnectTimeout)
+        );
+        assert_eq!(open_timeout(secs(9), 0, 0), None);
+    }
+
+    #[test]
+    fn slow_response_is_a_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            std::thread::sleep(Duration::from_secs(5));
+        });
+        let opts = FetchOptions::default()
+            .connect_timeout(Duration::from_secs(1))
+            .read_timeout(Duration::from_secs(2));
+        let rs = BlocklessHttp::open(&url, &opts);
+        assert_eq!(rs.err(), Some(HttpErrorKind::ReadTimeout));
+    }
+
+    #[test]
+    fn patch_json_sends_a_merge_patch() {
+        let (url, requests) = serve(vec![response("204 No Content", b"")]);
+        let http = patch_json(&url, &serde_json::json!({ "name": null })).unwrap();
+        assert_eq!(http.get_code(), 204);
+
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("PATCH / "));
+        assert!(request
+            .to_ascii_lowercase()
+            .contains("content-type: application/merge-patch+json\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"name\":null}"));
+    }
+
+    #[test]
+    fn last_host_code_only_follows_host_calls() {
+        let (url, _) = serve(vec![response("200 OK", b"")]);
+        let http = get(&url).unwrap();
+        assert_eq!(last_host_code(), None);
+
+        // Probing for headers the server did not send is not a failure...
+        assert!(http.get_all_headers().is_ok());
+        assert_eq!(http.headers().count(), 2);
+        assert_eq!(http.retry_after(), None);
+        assert_eq!(last_host_code(), None);
+        // ...unless the caller asked for that header.
+        assert_eq!(http.get_header("Etag"), Err(HttpErrorKind::HeaderNotFound));
+        assert_eq!(last_host_code(), Some(4));
+        assert_eq!(http.content_length(), Some(0));
+        assert_eq!(last_host_code(), None);
+        drop(http);
+
+        // Rejected before reaching the host.
+        assert_eq!(get("no scheme").err(), Some(HttpErrorKind::InvalidUrl));
+        assert_eq!(last_host_code(), None);
+
+        assert_eq!(
+            get("foo://127.0.0.1/").err(),
+            Some(HttpErrorKind::InvalidUrl)
+        );
+        assert_eq!(last_host_code(), Some(9));
+    }
+
+    #[test]
+    fn long_retry_after_is_not_waited_for() {
+        let (url, requests) = serve(vec![
+            response("429 Too Many Requests\r\nRetry-After: 0", b""),
+            response("429 Too Many Requests\r\nRetry-After: 86400", b""),
+            response("200 OK", b""),
+        ]);
+        let opts = FetchOptions::default().retry(3);
+        let started = Instant::now();
+        let http = BlocklessHttp::open(&url, &opts).unwrap();
+        assert_eq!(http.get_code(), 429);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(requests.iter().take(2).count(), 2);
+
+        let (url, _) = serve(vec![
+            response("429 Too Many Requests\r\nRetry-After: 1", b""),
+            response("200 OK", b""),
+        ]);
+        let opts = opts.max_retry_wait(Duration::ZERO);
+        assert_eq!(BlocklessHttp::open(&url, &opts).unwrap().get_code(), 429);
+    }
+
+    #[test]
+    fn json_error_bodies_are_parsed() {
+        let (url, _) = serve(vec![response(
+            "404 Not Found\r\nContent-Type: application/json",
+            br#"{"error":{"code":"missing"}}"#,
+        )]);
+        let http = get(&url).unwrap();
+        assert_eq!(
+            http.get_json_pointer("/error/code").unwrap(),
+            Some(Value::from("missing"))
+        );
+    }
+
+    #[test]
+    fn bom_is_stripped_before_decoding() {
+        let body = b"\xEF\xBB\xBF{\"usd\":1.5}";
+        let value: Value = Response::new(200, &body[..]).json().unwrap();
+        assert_eq!(value["usd"], 1.5);
+        let text = Response::new(200, &body[..]).text().unwrap();
+        assert_eq!(text, "{\"usd\":1.5}");
+
+        let (url, _) = serve(vec![response("200 OK", body)]);
+        let value: Value = get(&url).unwrap().get_json().unwrap();
+        assert_eq!(value["usd"], 1.5);
+    }
+
+    fn chunked(payload: &[u8]) -> Vec<u8> {
+        let head = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n";
+        let mut raw = head.as_bytes().to_vec();
+        raw.extend_from_slice(format!("{:x}\r\n", payload.len()).as_bytes());
+        raw.extend_from_slice(payload);
+        raw.extend_from_slice(b"\r\n0\r\n\r\n");
+        raw
+    }
+
+    fn decode(input: &[u8]) -> Result<Vec<u8>, HttpErrorKind> {
+        let mut decoder = ChunkDecoder::default();
+        decoder.feed(input)?;
+        decoder.finish()?;
+        let mut out = vec![0; input.len()];
+        let n = decoder.take(&mut out);
+        out.truncate(n);
+        Ok(out)
+    }
+
+    #[test]
+    fn chunk_decoder_strips_framing() {
+        let framed = b"4\r\nWiki\r\nB;name=value\r\npedia in \r\n\r\n0\r\nX-Trailer: 1\r\n\r\n";
+        assert_eq!(decode(framed).unwrap(), b"Wikipedia in \r\n");
+
+        let mut decoder = ChunkDecoder::default();
+        for b in framed.iter() {
+            decoder.feed(std::slice::from_ref(b)).unwrap();
+        }
+        assert!(decoder.finish().is_ok());
+        let mut out = [0u8; 32];
+        let n = decoder.take(&mut out);
+        assert_eq!(&out[..n], b"Wikipedia in \r\n");
+        assert!(decoder.is_done());
+
+        assert_eq!(decode(b"0\r\n").unwrap(), b"");
+        for bad in [
+            &b"4\r\nWikiX\r\n0\r\n\r\n"[..],
+            b"zz\r\n",
+            b"\r\n",
+            b"4\r\nWi",
+        ] {
+            assert!(matches!(decode(bad), Err(HttpErrorKind::InvalidEncoding)));
+        }
+        assert!(matches!(
+            decode(b"fffffffffffffffffffff\r\n"),
+            Err(HttpErrorKind::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn chunked_bodies_are_left_alone_by_default() {
+        let (url, _) = serve(vec![chunked(b"0\r\n"), chunked(b"1\r\nhello")]);
+        assert_eq!(get(&url).unwrap().get_all_body().unwrap(), b"0\r\n");
+        let http = get(&url).unwrap();
+        let read: Vec<u8> = http.body_chunks().flat_map(Result::unwrap).collect();
+        assert_eq!(read, b"1\r\nhello");
+    }
+
+    #[test]
+    fn decode_chunked_applies_to_every_read() {
+        let inner = b"4\r\nWiki\r\n0\r\n\r\n";
+        let (url, _) = serve(vec![chunked(inner); 5]);
+        let opts = FetchOptions::default().decode_chunked(true);
+        let open = |opts: &FetchOptions| BlocklessHttp::open(&url, opts).unwrap();
+
+        assert_eq!(open(&opts).get_all_body().unwrap(), b"Wiki");
+        let mut buf = Vec::with_capacity(4096);
+        assert_eq!(open(&opts).read_all_into(&mut buf).unwrap(), 4);
+        assert_eq!(buf, b"Wiki");
+        assert!(buf.capacity() >= 4096);
+        let streamed: Vec<u8> = open(&opts.clone().read_buffer_size(1))
+            .body_chunks()
+            .flat_map(Result::unwrap)
+            .collect();
+        assert_eq!(streamed, b"Wiki");
+        assert!(open(&opts.clone().max_body_size(4)).get_all_body().is_ok());
+        assert!(matches!(
+            open(&opts.clone().max_body_size(3)).get_all_body(),
+            Err(HttpErrorKind::BodyTooLarge)
+        ));
+
+        let (url, _) = serve(vec![chunked(b"4\r\nWikipedia\r\n0\r\n\r\n")]);
+        assert!(matches!(
+            BlocklessHttp::open(&url, &opts).unwrap().get_all_body(),
+            Err(HttpErrorKind::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn reopen_replaces_the_response() {
+        let (url, _) = serve(vec![
+            response("200 OK", b"first"),
+            response("404 Not Found", b"second"),
+        ]);
+        let mut http = get(&url).unwrap();
+        assert_eq!(http.reopen(&url, &FetchOptions::default()).unwrap(), 404);
+        assert_eq!(http.get_all_body().unwrap(), b"second");
+
+        // What the `TooManySessions` fallback leaves behind if reopening fails.
+        http.release();
+        http.release();
+        assert_eq!(http.get_all_body(), Err(HttpErrorKind::InvalidHandle));
+        assert_eq!(
+            http.get_header("Content-Length"),
+            Err(HttpErrorKind::InvalidHandle)
+        );
+    }
+
+    #[test]
+    fn locations_resolve_per_rfc_3986() {
+        // The normal and abnormal examples of RFC 3986 §5.4.
+        let base = "http://a/b/c/d;p?q";
+        for (reference, target) in [
+            ("g:h", "g:h"),
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("", "http://a/b/c/d;p?q"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g?y/../x", "http://a/b/c/g?y/../x"),
+            ("g#s/../x", "http://a/b/c/g#s/../x"),
+        ] {
+            assert_eq!(resolve_location(base, reference), target, "{}", reference);
+        }
+
+        assert_eq!(
+            resolve_location("http://h/a/b?x=1", "?page=2"),
+            "http://h/a/b?page=2"
+        );
+        assert_eq!(resolve_location("http://h", "next"), "http://h/next");
+        assert_eq!(resolve_location("https://h/a", "//cdn/x"), "https://cdn/x");
+        assert_eq!(
+            resolve_location("http://h/a", "HTTPS://other/./x/../y"),
+            "HTTPS://other/y"
+        );
+    }
+
+    #[test]
+    fn max_body_size_limits_every_read() {
+        use std::io::Read as _;
+
+        let (url, _) = serve(vec![response("200 OK", b"0123456789"); 5]);
+        let opts = FetchOptions::default().max_body_size(4);
+        let open = || BlocklessHttp::open(&url, &opts).unwrap();
+        let too_large = Some(HttpErrorKind::BodyTooLarge);
+
+        assert_eq!(open().peek(10).err(), too_large);
+        let err = open().read_to_end(&mut Vec::new()).unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<HttpErrorKind>();
+        assert_eq!(err.ok().map(|e| *e), too_large);
+        assert_eq!(open().body_chunks().find_map(Result::err), too_large);
+        assert_eq!(open().read_body(&mut [0u8; 64]).err(), too_large);
+        let rs = open().copy_to(&mut Vec::new(), |_| {});
+        assert_eq!(rs.err(), too_large);
+    }
+
+    #[test]
+    fn http_dates_parse() {
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784111777)
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 12:00:00 GMT"),
+            Some(1709208000)
+        );
+        assert_eq!(
+            parse_http_date("Wed, 01 Mar 2000 00:00:00 GMT"),
+            Some(951868800)
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+
+        for malformed in [
+            "",
+            "06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+        ] {
+            assert_eq!(parse_http_date(malformed), None, "{:?}", malformed);
+        }
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn slow_body_does_not_block_other_handles() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow = format!("http://{}", listener.local_addr().unwrap());
+        let (release, held) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+            let head = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\na";
+            stream.write_all(head).unwrap();
+            let _ = held.recv();
+            let _ = stream.write_all(b"b");
+        });
+        let (opened, reading) = mpsc::channel();
+        let reader = std::thread::spawn(move || {
+            let http = get(&slow).unwrap();
+            opened.send(()).unwrap();
+            http.get_all_body()
+        });
+        reading.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+
+        // The reader is now blocked inside the host, waiting for "b".
+        let (url, _) = serve(vec![response("200 OK", b"fast")]);
+        let (done, fast) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = done.send(get(&url).and_then(|http| http.get_all_body()));
+        });
+        let fast = fast.recv_timeout(Duration::from_secs(5));
+        release.send(()).unwrap();
+        assert_eq!(fast.unwrap().unwrap(), b"fast");
+        assert_eq!(reader.join().unwrap().unwrap(), b"ab");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::blockless::{BlocklessHttp, FetchOptions, HttpErrorKind, HttpMethod};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Defaults shared by many requests to one API: a base URL that paths are
+/// joined onto, headers, and timeouts. Cookies set by responses are kept and
+/// sent back on later requests.
+#[derive(Debug)]
+pub struct Client {
+    base_url: String,
+    headers: HashMap<String, String>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    cookies: RefCell<Vec<Cookie>>,
+}
+
+/// A cookie stored by a [`Client`]. Only the `Domain`, `Path`, `Max-Age` and
+/// `Secure` attributes are interpreted; `Expires` and the rest are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lowercase host the cookie belongs to.
+    pub domain: String,
+    /// Whether subdomains of `domain` get it too (a `Domain` attribute was set).
+    pub include_subdomains: bool,
+    pub path: String,
+    /// Sent only over `https://` (a `Secure` attribute was set).
+    pub secure: bool,
+}
+
+impl Cookie {
+    // Parses a `Set-Cookie` value received from `host`.
+    fn parse(set_cookie: &str, host: &str) -> Option<(Cookie, bool)> {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: host.to_string(),
+            include_subdomains: false,
+            path: "/".to_string(),
+            secure: false,
+        };
+        if cookie.name.is_empty() {
+            return None;
+        }
+        let mut expired = false;
+        for attr in parts {
+            let (key, value) = attr.split_once('=').unwrap_or((attr, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // A server may only set cookies for itself or a parent domain.
+                    if !domain_matches(host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.include_subdomains = true;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => expired = value.parse::<i64>().is_ok_and(|secs| secs <= 0),
+                _ => {}
+            }
+        }
+        Some((cookie, expired))
+    }
+
+    fn matches(&self, https: bool, host: &str, path: &str) -> bool {
+        let host_ok = if self.include_subdomains {
+            domain_matches(host, &self.domain)
+        } else {
+            host == self.domain
+        };
+        let path_ok = path == self.path
+            || path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/'));
+        host_ok && path_ok && (https || !self.secure)
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Lowercase host (without userinfo or port) and path of an absolute URL.
+fn host_and_path(url: &str) -> (String, String) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = match host.rfind(':') {
+        Some(i) if !host.ends_with(']') => &host[..i],
+        _ => host,
+    };
+    let rest = &rest[authority_end..];
+    let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+    let path = if path.is_empty() { "/" } else { path };
+    (host.to_ascii_lowercase(), path.to_string())
+}
+
+fn is_https(url: &str) -> bool {
+    url.get(..8)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+}
+
+impl Client {
+    pub fn new(base_url: &str) -> Self {
+        Client {
+            base_url: base_url.into(),
+            headers: HashMap::new(),
+            connect_timeout: None,
+            read_timeout: None,
+            cookies: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// A header sent with every request unless the request sets its own.
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers
+            .retain(|name, _| !name.eq_ignore_ascii_case(key));
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Asks for the connection to be kept open (`Connection: keep-alive`) or
+    /// closed after each response (`Connection: close`). The `blockless_http`
+    /// imports have no way to reuse a session, so every request still opens
+    /// its own handle; whether the host pools the underlying connection is
+    /// up to the host, and a host that does not simply ignores the header.
+    pub fn with_keep_alive(self, keep_alive: bool) -> Self {
+        let value = if keep_alive { "keep-alive" } else { "close" };
+        self.header("Connection", value)
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Options for `method` carrying this client's defaults, to be adjusted
+    /// per request and passed to [`Client::send`].
+    pub fn options(&self, method: HttpMethod) -> FetchOptions {
+        let mut opts = FetchOptions::with_method(method);
+        if let Some(timeout) = self.connect_timeout {
+            opts = opts.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            opts = opts.read_timeout(timeout);
+        }
+        opts
+    }
+
+    pub fn get(&self, path: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+        self.send(path, self.options(HttpMethod::Get))
+    }
+
+    pub fn post(&self, path: &str, body: &str) -> Result<BlocklessHttp, HttpErrorKind> {
+        self.send(path, self.options(HttpMethod::Post).body(body))
+    }
+
+    /// Sends `opts` to `path` joined onto the base URL. Default headers fill
+    /// in whatever `opts` does not set; timeouts are taken from `opts`, so
+    /// start from [`Client::options`] to inherit them.
+    /// Stored cookies matching the URL go out as a `Cookie` header unless
+    /// `opts` sets one itself, and `Set-Cookie` headers on the response update
+    /// the jar, checked against the URL that answered after any redirects.
+    /// `Secure` cookies only go to `https://` URLs.
+    pub fn send(&self, path: &str, mut opts: FetchOptions) -> Result<BlocklessHttp, HttpErrorKind> {
+        for (name, value) in &self.headers {
+            if !opts.has_header(name) {
+                opts = opts.header(name, value);
+            }
+        }
+        let url = self.url(path);
+        let (host, path) = host_and_path(&url);
+        let https = is_https(&url);
+        if !opts.has_header("Cookie") {
+            let cookies = self
+                .cookies
+                .borrow()
+                .iter()
+                .filter(|cookie| cookie.matches(https, &host, &path))
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>();
+            if !cookies.is_empty() {
+                opts = opts.header("Cookie", &cookies.join("; "));
+            }
+        }
+
+        let http = BlocklessHttp::open(&url, &opts)?;
+        // After redirects the cookies come from the last URL, not `url`.
+        let (host, _) = host_and_path(http.url());
+        if let Ok(Some(set_cookies)) = http.find_headers("Set-Cookie") {
+            let mut jar = self.cookies.borrow_mut();
+            for (cookie, expired) in set_cookies.iter().filter_map(|v| Cookie::parse(v, &host)) {
+                jar.retain(|c| {
+                    (&c.name, &c.domain, &c.path) != (&cookie.name, &cookie.domain, &cookie.path)
+                });
+                if !expired {
+                    jar.push(cookie);
+                }
+            }
+        }
+        Ok(http)
+    }
+
+    /// The cookies currently stored, oldest first.
+    pub fn cookie_jar(&self) -> Vec<Cookie> {
+        self.cookies.borrow().clone()
+    }
+
+    pub fn clear_cookies(&self) {
+        self.cookies.borrow_mut().clear();
+    }
+
+    /// `path` joined onto the base URL with exactly one `/` between them.
+    /// Absolute URLs are used as they are.
+    pub fn url(&self, path: &str) -> String {
+        if path.contains("://") {
+            return path.to_string();
+        }
+        let base = self.base_url.trim_end_matches('/');
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            return base.to_string();
+        }
+        format!("{}/{}", base, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockless::tests::{response, serve};
+
+    fn cookie(set_cookie: &str, host: &str) -> Cookie {
+        Cookie::parse(set_cookie, host).unwrap().0
+    }
+
+    #[test]
+    fn domain_and_path_matching() {
+        let host_only = cookie("a=1", "api.example.com");
+        assert!(host_only.matches(false, "api.example.com", "/"));
+        assert!(!host_only.matches(false, "v2.api.example.com", "/"));
+
+        let shared = cookie("a=1; Domain=.Example.com; Path=/v1", "api.example.com");
+        assert_eq!(shared.domain, "example.com");
+        assert!(shared.matches(false, "example.com", "/v1"));
+        assert!(shared.matches(false, "www.example.com", "/v1/prices"));
+        assert!(!shared.matches(false, "badexample.com", "/v1"));
+        assert!(!shared.matches(false, "www.example.com", "/v10"));
+        assert!(!shared.matches(false, "www.example.com", "/"));
+
+        assert!(Cookie::parse("a=1; Domain=other.com", "api.example.com").is_none());
+        assert!(Cookie::parse("a=1; Domain=api.example.com.evil", "api.example.com").is_none());
+    }
+
+    #[test]
+    fn secure_cookies_need_https() {
+        let secure = cookie("a=1; Secure", "example.com");
+        assert!(secure.matches(true, "example.com", "/"));
+        assert!(!secure.matches(false, "example.com", "/"));
+        assert!(is_https("HTTPS://example.com"));
+        assert!(!is_https("http://example.com"));
+    }
+
+    // The `Cookie` header of the next request the server saw.
+    fn sent_cookie(requests: &std::sync::mpsc::Receiver<String>) -> Option<String> {
+        let request = requests.recv().unwrap();
+        request.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("cookie")
+                .then(|| value.trim().to_string())
+        })
+    }
+
+    #[test]
+    fn max_age_zero_deletes() {
+        let (url, requests) = serve(vec![
+            response("200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2", b""),
+            response("200 OK\r\nSet-Cookie: a=gone; Max-Age=0", b""),
+            response("200 OK", b""),
+        ]);
+        let client = Client::new(&url);
+        client.get("/").unwrap();
+        client.get("/").unwrap();
+        assert_eq!(sent_cookie(&requests), None);
+        assert_eq!(sent_cookie(&requests).as_deref(), Some("a=1; b=2"));
+        let names: Vec<_> = client.cookie_jar().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, ["b"]);
+        client.get("/").unwrap();
+        assert_eq!(sent_cookie(&requests).as_deref(), Some("b=2"));
+    }
+
+    #[test]
+    fn redirected_cookies_belong_to_the_target() {
+        let (target, _) = serve(vec![response(
+            "200 OK\r\nSet-Cookie: a=1; Domain=127.0.0.1\r\nSet-Cookie: b=2",
+            b"",
+        )]);
+        let target = target.replace("127.0.0.1", "localhost");
+        let (url, _) = serve(vec![response(
+            &format!("302 Found\r\nLocation: {}/", target),
+            b"",
+        )]);
+        let client = Client::new(&url);
+        let opts = client.options(HttpMethod::Get).follow_redirects(1);
+        assert_ne!(client.send("/", opts).unwrap().url(), url);
+        let jar = client.cookie_jar();
+        assert_eq!(jar.len(), 1);
+        assert_eq!(
+            (jar[0].name.as_str(), jar[0].domain.as_str()),
+            ("b", "localhost")
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::blockless::{FetchError, FetchOptions, HostClient, HttpClient, HttpMethod};
+use crate::encoding::strip_bom;
+use serde::{de::Error as _, Deserialize, Serialize};
+use std::collections::HashMap;
+
+const SIMPLE_PRICE_URL: &str = "https://api.coingecko.com/api/v3/simple/price";
+
+/// A price in one currency. `price` is fixed point with six decimal places
+/// (`1_500_000` is 1.5), so the record round-trips through its own JSON
+/// exactly; [`CoinPriceData::price_f64`] gives the decimal value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoinPriceData {
+    pub id: String,
+    pub price: u64,
+    pub currency: String,
+}
+
+const PRICE_SCALE: f64 = 1_000_000.0;
+
+impl CoinPriceData {
+    /// Converts a decimal price, rounding to the nearest millionth. Negative
+    /// and NaN prices become 0.
+    pub fn from_f64(id: &str, price: f64, currency: &str) -> Self {
+        CoinPriceData {
+            id: id.to_string(),
+            price: (price * PRICE_SCALE).round() as u64,
+            currency: currency.to_string(),
+        }
+    }
+
+    pub fn from_usd(id: &str, price: f64) -> Self {
+        Self::from_f64(id, price, "usd")
+    }
+
+    pub fn price_f64(&self) -> f64 {
+        self.price as f64 / PRICE_SCALE
+    }
+}
+
+// Keyed by coin id, then by currency.
+type SuccessResponse = HashMap<String, HashMap<String, f64>>;
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    status: ErrorStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorStatus {
+    error_code: u32,
+    error_message: String,
+}
+
+/// Looks up the current price of `coin_id` in `currency` on CoinGecko.
+pub fn fetch_coin_price(coin_id: &str, currency: &str) -> Result<CoinPriceData, FetchError> {
+    fetch_coin_price_with(&HostClient, coin_id, currency)
+}
+
+/// [`fetch_coin_price`] over any [`HttpClient`], e.g. a mock in tests.
+pub fn fetch_coin_price_with(
+    client: &impl HttpClient,
+    coin_id: &str,
+    currency: &str,
+) -> Result<CoinPriceData, FetchError> {
+    fetch_coin_prices_with(client, &[coin_id], &[currency])?
+        .pop()
+        .ok_or_else(|| {
+            let message = format!("no {} price for {}", currency, coin_id);
+            FetchError::Parse(serde_json::Error::custom(message))
+        })
+}
+
+/// Looks up every `coin_ids` x `currencies` pair in one request. Prices come
+/// back grouped by coin in the order requested; pairs CoinGecko has no price
+/// for are left out.
+pub fn fetch_coin_prices(
+    coin_ids: &[&str],
+    currencies: &[&str],
+) -> Result<Vec<CoinPriceData>, FetchError> {
+    fetch_coin_prices_with(&HostClient, coin_ids, currencies)
+}
+
+/// [`fetch_coin_prices`] over any [`HttpClient`], e.g. a mock in tests.
+pub fn fetch_coin_prices_with(
+    client: &impl HttpClient,
+    coin_ids: &[&str],
+    currencies: &[&str],
+) -> Result<Vec<CoinPriceData>, FetchError> {
+    let opts = FetchOptions::with_method(HttpMethod::Get).queries(&[
+        ("ids", &coin_ids.join(",")),
+        ("vs_currencies", &currencies.join(",")),
+    ]);
+    let response = client.open(SIMPLE_PRICE_URL, &opts)?;
+    let status = response.status();
+    let body = response.into_body()?;
+    let body = strip_bom(&body);
+
+    // Try to parse as success response first
+    match serde_json::from_slice::<SuccessResponse>(body) {
+        Ok(prices) => {
+            let mut found = Vec::new();
+            for &id in coin_ids {
+                for &currency in currencies {
+                    if let Some(price) = prices
+                        .get(id)
+                        .and_then(|by_currency| by_currency.get(currency))
+                    {
+                        found.push(CoinPriceData::from_f64(id, *price, currency));
+                    }
+                }
+            }
+            Ok(found)
+        }
+        // If it's not a success response, try to parse as error response
+        Err(e) => match serde_json::from_slice::<ErrorResponse>(body) {
+            Ok(error_response) => Err(FetchError::Api(
+                error_response.status.error_code,
+                error_response.status.error_message,
+            )),
+            Err(_) if !status.is_success() => Err(FetchError::Status(status.as_u16())),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockHttpClient;
+
+    #[test]
+    fn price_body_with_bom() {
+        let client = MockHttpClient::with_response(200, "\u{feff}{\"bitcoin\":{\"usd\":65000.5}}");
+        let price = fetch_coin_price_with(&client, "bitcoin", "usd").unwrap();
+        assert_eq!(price, CoinPriceData::from_usd("bitcoin", 65000.5));
+    }
+}
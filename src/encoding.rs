@@ -0,0 +1,134 @@
+use crate::blockless::HttpErrorKind;
+
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+fn encode_except(input: &str, keep: impl Fn(u8) -> bool) -> String {
+    let mut out = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        if keep(b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// Percent-encodes everything except the RFC 3986 unreserved characters,
+/// which makes the result safe as a query key or value or a single path
+/// segment.
+pub fn percent_encode(input: &str) -> String {
+    encode_except(input, is_unreserved)
+}
+
+/// Like [`percent_encode`] but keeps `/`, for encoding a whole path.
+pub fn percent_encode_path(input: &str) -> String {
+    encode_except(input, |b| is_unreserved(b) || b == b'/')
+}
+
+/// Reverses percent-encoding. `+` is left alone, as RFC 3986 gives it no
+/// special meaning. A `%` not followed by two hex digits is
+/// `InvalidEncoding`; decoded bytes that are not UTF-8 are `Utf8Error`.
+pub fn percent_decode(input: &str) -> Result<String, HttpErrorKind> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(HttpErrorKind::InvalidEncoding)?;
+            out.push(hex);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| HttpErrorKind::Utf8Error)
+}
+
+/// Percent-encodes bytes that may never appear literally in a URL, leaving
+/// reserved characters and existing `%XX` escapes alone.
+pub(crate) fn encode_unsafe(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for &b in input.as_bytes() {
+        let unsafe_byte = b <= b' '
+            || b >= 0x7f
+            || matches!(
+                b,
+                b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}'
+            );
+        if unsafe_byte {
+            out.push_str(&format!("%{:02X}", b));
+        } else {
+            out.push(b as char);
+        }
+    }
+    out
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Drops a leading UTF-8 byte order mark, which some servers put in front
+/// of text and JSON bodies and which `serde_json` rejects.
+pub(crate) fn strip_bom(body: &[u8]) -> &[u8] {
+    body.strip_prefix(UTF8_BOM).unwrap_or(body)
+}
+
+/// Decodes a text body according to the `charset` parameter of its
+/// `Content-Type`: UTF-8 (and its subset US-ASCII) or ISO-8859-1. No charset
+/// means UTF-8; any other charset is `InvalidEncoding`. A UTF-8 byte order
+/// mark is dropped.
+pub(crate) fn decode_text(
+    mut body: Vec<u8>,
+    content_type: Option<&str>,
+) -> Result<String, HttpErrorKind> {
+    let charset = content_type
+        .into_iter()
+        .flat_map(|value| value.split(';').skip(1))
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_ascii_lowercase());
+    match charset.as_deref() {
+        None | Some("utf-8" | "utf8" | "us-ascii" | "ascii") => {
+            if body.starts_with(UTF8_BOM) {
+                body.drain(..UTF8_BOM.len());
+            }
+            String::from_utf8(body).map_err(|_| HttpErrorKind::Utf8Error)
+        }
+        Some("iso-8859-1" | "iso8859-1" | "latin1" | "latin-1" | "l1") => {
+            Ok(body.iter().map(|&b| b as char).collect())
+        }
+        Some(_) => Err(HttpErrorKind::InvalidEncoding),
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard (RFC 4648) base64 with padding.
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,36 @@
+// Logging hooks: forwarded to the `log` crate with the `log` feature and
+// compiled away (arguments still type-checked) without it.
+macro_rules! log_debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+macro_rules! log_warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+// Without the wasm imports or the `native` stand-ins there is nothing to
+// link `blockless_http` against; say so instead of failing at link time.
+#[cfg(not(any(target_arch = "wasm32", feature = "native")))]
+compile_error!("this crate requires target wasm32 or the `native` feature");
+
+pub mod blockless;
+pub mod client;
+pub mod coingecko;
+pub mod encoding;
+#[cfg(feature = "test-util")]
+pub mod mock;
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+mod native;
+#[cfg(feature = "record")]
+pub mod record;
+pub mod url;
@@ -0,0 +1,42 @@
+use crate::blockless::{append_query, FetchOptions, HttpClient, HttpErrorKind, Response};
+use std::cell::RefCell;
+
+type Handler = dyn Fn(&str, &FetchOptions) -> Result<Response, HttpErrorKind>;
+
+/// [`HttpClient`] that answers from a closure instead of the host, so code
+/// written against the trait can be tested offline. The handler receives the
+/// full URL, including any query parameters from the options.
+pub struct MockHttpClient {
+    handler: Box<Handler>,
+    requests: RefCell<Vec<String>>,
+}
+
+impl MockHttpClient {
+    pub fn new(
+        handler: impl Fn(&str, &FetchOptions) -> Result<Response, HttpErrorKind> + 'static,
+    ) -> Self {
+        MockHttpClient {
+            handler: Box::new(handler),
+            requests: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Answers every request with `code` and `body`.
+    pub fn with_response(code: u32, body: &str) -> Self {
+        let body = body.to_string();
+        Self::new(move |_, _| Ok(Response::new(code, body.clone())))
+    }
+
+    /// URLs requested so far, oldest first.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.borrow().clone()
+    }
+}
+
+impl HttpClient for MockHttpClient {
+    fn open(&self, url: &str, opts: &FetchOptions) -> Result<Response, HttpErrorKind> {
+        let url = append_query(url, opts.query_pairs());
+        self.requests.borrow_mut().push(url.clone());
+        (self.handler)(&url, opts)
+    }
+}
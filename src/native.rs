@@ -0,0 +1,188 @@
+//! Host-side stand-ins for the `blockless_http` imports, backed by `ureq`.
+//!
+//! The functions mirror the wasm imports one for one, so everything above the
+//! FFI boundary in [`crate::blockless`] is shared between both backends.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// Return codes understood by `HttpErrorKind::from`.
+const INVALID_HANDLE: u32 = 1;
+const HEADER_NOT_FOUND: u32 = 4;
+const UTF8_ERROR: u32 = 5;
+const INVALID_ENCODING: u32 = 8;
+const INVALID_URL: u32 = 9;
+const REQUEST_ERROR: u32 = 10;
+
+struct Session {
+    headers: HashMap<String, String>,
+    header_offsets: HashMap<String, usize>,
+    body: Box<dyn Read + Send + Sync>,
+}
+
+// Each session has its own lock, so a slow body read only blocks calls on
+// that handle; the map's lock is held just long enough to look one up.
+fn sessions() -> &'static Mutex<HashMap<u32, Arc<Mutex<Session>>>> {
+    static SESSIONS: OnceLock<Mutex<HashMap<u32, Arc<Mutex<Session>>>>> = OnceLock::new();
+    SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn session(handle: u32) -> Option<Arc<Mutex<Session>>> {
+    sessions().lock().unwrap().get(&handle).cloned()
+}
+
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+
+unsafe fn str_arg<'a>(ptr: *const u8, len: u32) -> Result<&'a str, u32> {
+    let bytes = unsafe { std::slice::from_raw_parts(ptr, len as usize) };
+    std::str::from_utf8(bytes).map_err(|_| UTF8_ERROR)
+}
+
+fn send(url: &str, opts: &str) -> Result<ureq::Response, u32> {
+    let opts: Value = serde_json::from_str(opts).map_err(|_| INVALID_ENCODING)?;
+    let secs = |key: &str| {
+        opts[key]
+            .as_u64()
+            .filter(|&s| s > 0)
+            .map(Duration::from_secs)
+    };
+
+    let mut agent = ureq::AgentBuilder::new().redirects(0);
+    if let Some(timeout) = secs("connectTimeout") {
+        agent = agent.timeout_connect(timeout);
+    }
+    if let Some(timeout) = secs("readTimeout") {
+        agent = agent.timeout_read(timeout);
+    }
+
+    let method = opts["method"].as_str().unwrap_or("GET");
+    let mut request = agent.build().request(method, url);
+    if let Some(headers) = opts["headers"].as_object() {
+        for (name, value) in headers {
+            request = request.set(name, value.as_str().unwrap_or_default());
+        }
+    }
+
+    let result = match opts["body"].as_str() {
+        Some(body) => request.send_string(body),
+        None => request.call(),
+    };
+    match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(response),
+        Err(ureq::Error::Transport(transport)) => match transport.kind() {
+            ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => Err(INVALID_URL),
+            _ => Err(REQUEST_ERROR),
+        },
+    }
+}
+
+pub(crate) unsafe fn http_open(
+    url: *const u8,
+    url_len: u32,
+    opts: *const u8,
+    opts_len: u32,
+    fd: *mut u32,
+    status: *mut u32,
+) -> u32 {
+    let (url, opts) = match unsafe { (str_arg(url, url_len), str_arg(opts, opts_len)) } {
+        (Ok(url), Ok(opts)) => (url, opts),
+        (Err(rs), _) | (_, Err(rs)) => return rs,
+    };
+    let response = match send(url, opts) {
+        Ok(response) => response,
+        Err(rs) => return rs,
+    };
+
+    // Repeated headers are joined with newlines, one line per occurrence.
+    // The ABI does not define a format for repeats; this is our own choice.
+    let mut headers = HashMap::new();
+    for name in response.headers_names() {
+        let values = response.all(&name);
+        if !values.is_empty() {
+            headers
+                .entry(name.to_ascii_lowercase())
+                .or_insert_with(|| values.join("\n"));
+        }
+    }
+    let code = response.status() as u32;
+    let session = Session {
+        headers,
+        header_offsets: HashMap::new(),
+        body: response.into_reader(),
+    };
+
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    sessions()
+        .lock()
+        .unwrap()
+        .insert(handle, Arc::new(Mutex::new(session)));
+    unsafe {
+        *fd = handle;
+        *status = code;
+    }
+    0
+}
+
+/// Copies the next piece of the header value into `buf`; a call that copies
+/// nothing marks the end and rewinds, matching how the guest drains headers.
+pub(crate) unsafe fn http_read_header(
+    handle: u32,
+    header: *const u8,
+    header_len: u32,
+    buf: *mut u8,
+    buf_len: u32,
+    num: *mut u32,
+) -> u32 {
+    let name = match unsafe { str_arg(header, header_len) } {
+        Ok(name) => name.to_ascii_lowercase(),
+        Err(rs) => return rs,
+    };
+    let Some(session) = session(handle) else {
+        return INVALID_HANDLE;
+    };
+    let mut session = session.lock().unwrap();
+    let session = &mut *session;
+    let Some(value) = session.headers.get(&name) else {
+        return HEADER_NOT_FOUND;
+    };
+
+    let offset = session.header_offsets.get(&name).copied().unwrap_or(0);
+    let rest = &value.as_bytes()[offset..];
+    let n = rest.len().min(buf_len as usize);
+    unsafe {
+        std::ptr::copy_nonoverlapping(rest.as_ptr(), buf, n);
+        *num = n as u32;
+    }
+    if n == 0 {
+        session.header_offsets.remove(&name);
+    } else {
+        session.header_offsets.insert(name, offset + n);
+    }
+    0
+}
+
+pub(crate) unsafe fn http_read_body(handle: u32, buf: *mut u8, buf_len: u32, num: *mut u32) -> u32 {
+    let Some(session) = session(handle) else {
+        return INVALID_HANDLE;
+    };
+    let mut session = session.lock().unwrap();
+    let buf = unsafe { std::slice::from_raw_parts_mut(buf, buf_len as usize) };
+    match session.body.read(buf) {
+        Ok(n) => {
+            unsafe { *num = n as u32 };
+            0
+        }
+        Err(_) => REQUEST_ERROR,
+    }
+}
+
+pub(crate) unsafe fn http_close(handle: u32) -> u32 {
+    match sessions().lock().unwrap().remove(&handle) {
+        Some(_) => 0,
+        None => INVALID_HANDLE,
+    }
+}
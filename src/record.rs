@@ -0,0 +1,178 @@
+use crate::blockless::{
+    append_query, is_credential_header, FetchOptions, HttpClient, HttpErrorKind, Response,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+/// One request and the response it got, as written by [`RecordingClient`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// Full URL, including query parameters from the options.
+    pub url: String,
+    /// The options as handed to the host, see [`crate::blockless::HttpOptions::to_json`],
+    /// with `Authorization`, `Cookie` and `Proxy-Authorization` values
+    /// replaced by [`REDACTED`].
+    pub request: Value,
+    pub status: u32,
+    /// Lowercase names; limited to what [`crate::blockless::BlocklessHttp::get_all_headers`] finds.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Exchange {
+    fn into_response(self) -> Response {
+        let mut response = Response::new(self.status, self.body).with_url(&self.url);
+        for (name, value) in &self.headers {
+            for value in value.split('\n') {
+                response = response.with_header(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Stands in for credential header values in a recording.
+pub const REDACTED: &str = "[redacted]";
+
+/// [`HttpClient`] that passes requests to `inner` and writes each exchange
+/// to `sink` as one line of JSON. Credentials sent in request headers are
+/// not written, so recordings can be shared. Responses are read in full before they are
+/// returned, so the handle is released right away.
+pub struct RecordingClient<C, W> {
+    inner: C,
+    sink: RefCell<W>,
+}
+
+impl<C: HttpClient, W: Write> RecordingClient<C, W> {
+    pub fn new(inner: C, sink: W) -> Self {
+        RecordingClient {
+            inner,
+            sink: RefCell::new(sink),
+        }
+    }
+
+    pub fn into_inner(self) -> (C, W) {
+        (self.inner, self.sink.into_inner())
+    }
+}
+
+impl<C: HttpClient, W: Write> HttpClient for RecordingClient<C, W> {
+    fn open(&self, url: &str, opts: &FetchOptions) -> Result<Response, HttpErrorKind> {
+        let response = self.inner.open(url, opts)?;
+        let status = response.get_code();
+        let headers = response.all_headers()?;
+        let body = response.into_body()?;
+
+        let mut request = opts.http_options().to_json();
+        if let Some(headers) = request["headers"].as_object_mut() {
+            for (name, value) in headers.iter_mut() {
+                if is_credential_header(name) {
+                    *value = REDACTED.into();
+                }
+            }
+        }
+        let exchange = Exchange {
+            url: append_query(url, opts.query_pairs()),
+            request,
+            status,
+            headers,
+            body,
+        };
+        let mut sink = self.sink.borrow_mut();
+        serde_json::to_writer(&mut *sink, &exchange)?;
+        writeln!(sink).map_err(|e| HttpErrorKind::Io(e.kind()))?;
+
+        Ok(exchange.into_response())
+    }
+}
+
+/// [`HttpClient`] that answers from exchanges recorded by
+/// [`RecordingClient`] instead of the host. Each request takes the first
+/// unused exchange with the same URL and method; when there is none the
+/// request fails with `RequestError`.
+pub struct ReplayClient {
+    exchanges: RefCell<Vec<Exchange>>,
+}
+
+impl ReplayClient {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        ReplayClient {
+            exchanges: RefCell::new(exchanges),
+        }
+    }
+
+    /// Reads a recording, one exchange per line. Blank lines are skipped.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, HttpErrorKind> {
+        let mut exchanges = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(|e| HttpErrorKind::Io(e.kind()))?;
+            if !line.trim().is_empty() {
+                exchanges.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self::new(exchanges))
+    }
+
+    /// Exchanges not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.borrow().len()
+    }
+}
+
+impl HttpClient for ReplayClient {
+    fn open(&self, url: &str, opts: &FetchOptions) -> Result<Response, HttpErrorKind> {
+        let url = append_query(url, opts.query_pairs());
+        let method = opts.http_options().to_json()["method"].clone();
+        let mut exchanges = self.exchanges.borrow_mut();
+        let index = exchanges
+            .iter()
+            .position(|e| e.url == url && e.request["method"] == method)
+            .ok_or(HttpErrorKind::RequestError)?;
+        Ok(exchanges.remove(index).into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockless::tests::{response, serve};
+    use crate::blockless::HostClient;
+
+    #[test]
+    fn replay_keeps_repeated_headers() {
+        let (url, _) = serve(vec![response(
+            "200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2",
+            b"hello",
+        )]);
+        let recorder = RecordingClient::new(HostClient, Vec::new());
+        let recorded = recorder.open(&url, &FetchOptions::default()).unwrap();
+        assert_eq!(recorded.get_headers("Set-Cookie").unwrap(), ["a=1", "b=2"]);
+
+        let (_, recording) = recorder.into_inner();
+        let replay = ReplayClient::from_reader(&recording[..]).unwrap();
+        let replayed = replay.open(&url, &FetchOptions::default()).unwrap();
+        assert_eq!(replayed.get_headers("Set-Cookie").unwrap(), ["a=1", "b=2"]);
+        assert_eq!(replayed.into_body().unwrap(), b"hello");
+    }
+
+    #[test]
+    fn recordings_leave_out_credentials() {
+        let (url, requests) = serve(vec![response("200 OK", b"")]);
+        let opts = FetchOptions::default()
+            .header("Authorization", "Bearer secret-token")
+            .header("cookie", "session=secret-session");
+        let recorder = RecordingClient::new(HostClient, Vec::new());
+        recorder.open(&url, &opts).unwrap();
+        assert!(requests.recv().unwrap().contains("Bearer secret-token"));
+
+        let (_, recording) = recorder.into_inner();
+        let recording = String::from_utf8(recording).unwrap();
+        assert!(!recording.contains("secret"));
+        let exchange: Exchange = serde_json::from_str(&recording).unwrap();
+        assert_eq!(exchange.request["headers"]["Authorization"], REDACTED);
+        assert_eq!(exchange.request["headers"]["cookie"], REDACTED);
+    }
+}
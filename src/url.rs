@@ -0,0 +1,50 @@
+use crate::blockless::append_query;
+use crate::encoding::percent_encode;
+
+/// Assembles a URL from a base, path segments and query parameters, taking
+/// care of the slashes between segments and of percent-encoding.
+#[derive(Debug, Clone)]
+pub struct UrlBuilder {
+    base: String,
+    segments: Vec<String>,
+    query: Vec<(String, String)>,
+}
+
+impl UrlBuilder {
+    /// `base` is used as given, apart from trailing slashes, and may already
+    /// contain a path.
+    pub fn new(base: &str) -> Self {
+        UrlBuilder {
+            base: base.trim_end_matches('/').into(),
+            segments: Vec::new(),
+            query: Vec::new(),
+        }
+    }
+
+    /// Appends one path segment. It is percent-encoded, so a `/` inside it
+    /// stays part of the segment.
+    pub fn path_segment(mut self, segment: &str) -> Self {
+        self.segments.push(percent_encode(segment));
+        self
+    }
+
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let mut url = self.base.clone();
+        for segment in &self.segments {
+            url.push('/');
+            url.push_str(segment);
+        }
+        append_query(&url, &self.query)
+    }
+}
+
+impl From<UrlBuilder> for String {
+    fn from(builder: UrlBuilder) -> String {
+        builder.build()
+    }
+}